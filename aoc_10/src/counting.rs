@@ -0,0 +1,165 @@
+use super::Map;
+
+// A summit can be at most 9 steps away, since elevations are single digits and every step climbs,
+// so the summits reachable from a cell all fit in a 19 × 19 window centred on it
+const MAX_RADIUS: usize = 9;
+const WORDS: usize = ((2 * MAX_RADIUS + 1) * (2 * MAX_RADIUS + 1)).div_ceil(64);
+
+// Summits by their position relative to some cell, one bit per cell of the window around it (row by
+// row). Moving the window one cell over is then just a shift, whichever cell it's centred on.
+#[derive(Clone, Copy, Default)]
+struct SummitSet([u64; WORDS]);
+
+impl SummitSet {
+    fn insert(&mut self, bit: usize) {
+        self.0[bit / 64] |= 1 << (bit % 64);
+    }
+
+    // Adds every summit in `other`, first moving each up by `shift` bits (down if negative). Bits
+    // pushed off either end of the window are dropped.
+    fn union_shifted(&mut self, other: &SummitSet, shift: isize) {
+        let (words, bits) = (shift.unsigned_abs() / 64, shift.unsigned_abs() % 64);
+        for i in 0..WORDS {
+            let word = if shift >= 0 {
+                let lower = i.checked_sub(words + 1).filter(|_| bits > 0).map_or(0, |j| other.0[j] >> (64 - bits));
+                i.checked_sub(words).map_or(0, |j| other.0[j] << bits) | lower
+            } else {
+                let upper = other.0.get(i + words + 1).filter(|_| bits > 0).map_or(0, |w| w << (64 - bits));
+                other.0.get(i + words).map_or(0, |w| w >> bits) | upper
+            };
+            self.0[i] |= word;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.0.iter().map(|word| word.count_ones() as usize).sum()
+    }
+}
+
+// Score and rating of every cell, as if it were a trailhead
+pub struct TrailCounts {
+    pub scores: Vec<Vec<usize>>,
+    pub ratings: Vec<Vec<usize>>,
+}
+
+impl Map {
+    // Works down from the summit one elevation at a time, so each cell's trails are counted once
+    // from those of the cells it can step onto. Only the summit sets of elevations that can still be
+    // stepped onto are kept around.
+    pub fn get_all_scores_and_ratings(&self) -> TrailCounts {
+        let top = self.rules.summit.min(MAX_RADIUS as u8);
+        let min_step = *self.rules.steps.iter().min().unwrap();
+        let max_step = *self.rules.steps.iter().max().unwrap();
+        let radius = top as usize / min_step as usize;
+        let window = 2 * radius + 1;
+        let center = radius * window + radius;
+
+        // Cells of each elevation, with each cell's index among those of its elevation
+        let mut levels: Vec<Vec<(usize, usize)>> = vec![vec![]; top as usize + 1];
+        let mut level_idx: Vec<Vec<usize>> = vec![vec![0; self.width]; self.height];
+        for (row, line) in self.elevations.iter().enumerate() {
+            for (col, &elevation) in line.iter().enumerate() {
+                if elevation <= top {
+                    level_idx[row][col] = levels[elevation as usize].len();
+                    levels[elevation as usize].push((row, col));
+                }
+            }
+        }
+
+        let mut scores: Vec<Vec<usize>> = vec![vec![0; self.width]; self.height];
+        let mut ratings: Vec<Vec<usize>> = vec![vec![0; self.width]; self.height];
+        let mut sets: Vec<Option<Vec<SummitSet>>> = vec![None; top as usize + 1];
+        if self.rules.summit > top {
+            // No summits on the map at all
+            return TrailCounts{scores, ratings};
+        }
+
+        for elevation in (0..=top as usize).rev() {
+            let mut level_sets: Vec<SummitSet> = vec![SummitSet::default(); levels[elevation].len()];
+            for (i, &coord) in levels[elevation].iter().enumerate() {
+                if elevation == top as usize {
+                    level_sets[i].insert(center);
+                    ratings[coord.0][coord.1] = 1;
+                } else {
+                    for next in self.next_steps(coord) {
+                        // Steps only ever climb, so the next cell's level has already been done
+                        let next_elevation = self.elevations[next.0][next.1] as usize;
+                        debug_assert!(next_elevation > elevation);
+                        let next_set = &sets[next_elevation].as_ref().expect("levels above are counted first")[level_idx[next.0][next.1]];
+                        let shift = (next.0 as isize - coord.0 as isize) * window as isize + (next.1 as isize - coord.1 as isize);
+                        level_sets[i].union_shifted(next_set, shift);
+                        ratings[coord.0][coord.1] += ratings[next.0][next.1];
+                    }
+                }
+                scores[coord.0][coord.1] = level_sets[i].len();
+            }
+
+            sets[elevation] = Some(level_sets);
+            if let Some(unreachable) = sets.get_mut(elevation + max_step as usize) {
+                *unreachable = None;
+            }
+        }
+
+        TrailCounts{scores, ratings}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TrailRules;
+
+    fn map(text: &str, steps: Vec<u8>, summit: u8) -> Map {
+        let mut map = Map::from_lines(&text.lines().map(|line| line.trim().to_owned()).collect::<Vec<String>>()).unwrap();
+        map.rules = TrailRules::new(steps, summit).unwrap();
+        map
+    }
+
+    fn assert_matches_dfs(map: &Map) {
+        let counts = map.get_all_scores_and_ratings();
+        for &(row, col) in map.trailheads.iter() {
+            assert_eq!((counts.scores[row][col], counts.ratings[row][col]), map.get_trailhead_score_and_rating((row, col)));
+        }
+    }
+
+    #[test]
+    fn summit_set_shifts_both_ways_across_words() {
+        let mut set = SummitSet::default();
+        set.insert(0);
+        set.insert(63);
+        set.insert(WORDS * 64 - 1);
+
+        let mut up = SummitSet::default();
+        up.union_shifted(&set, 65);
+        assert_eq!(up.0[1], 1 << 1);
+        assert_eq!(up.0[2], 1);
+        assert_eq!(up.len(), 2); // The top bit is pushed off the end
+
+        let mut down = SummitSet::default();
+        down.union_shifted(&up, -65);
+        assert_eq!(down.0[0], (1 << 0) | (1 << 63));
+        assert_eq!(down.len(), 2);
+    }
+
+    #[test]
+    fn matches_dfs_with_skipped_elevations_and_low_summits() {
+        let text = "0123456789
+                    1234567898
+                    2305678987
+                    3456789876
+                    9876543210";
+        for steps in [vec![1], vec![2], vec![1, 2], vec![1, 3, 9], vec![9]] {
+            for summit in [9, 5, 1] {
+                assert_matches_dfs(&map(text, steps.clone(), summit));
+            }
+        }
+    }
+
+    #[test]
+    fn no_summits_on_the_map() {
+        let map = map("0123\n1234", vec![1], 9);
+        let counts = map.get_all_scores_and_ratings();
+        assert!(counts.scores.iter().flatten().all(|&score| score == 0));
+        assert!(counts.ratings.iter().flatten().all(|&rating| rating == 0));
+    }
+}
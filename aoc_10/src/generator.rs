@@ -0,0 +1,170 @@
+use util::res::Result;
+use super::Map;
+
+// How many branching probabilities to try when aiming for a target score or rating
+const TARGET_ATTEMPTS: usize = 12;
+
+// SplitMix64, so that maps only depend on the seed and not on some crate's version
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng{state: seed}
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    pub fn chance(&mut self, p: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Target {
+    Score(f64), // Mean score per trailhead
+    Rating(f64), // Mean rating per trailhead
+}
+
+pub struct MapSpec {
+    pub width: usize,
+    pub height: usize,
+    pub trailhead_density: f64, // Share of cells that are trailheads
+    pub target: Option<Target>,
+    pub seed: u64,
+}
+
+impl MapSpec {
+    // Without a target, trails fork at a quarter of their steps. With one, the chance of forking is
+    // bisected until the map's mean score or rating per trailhead is as close to it as can be found.
+    pub fn generate(&self) -> Result<Vec<String>> {
+        let Some(target) = self.target else {
+            return Ok(self.generate_with_branching(0.25));
+        };
+
+        let mut best: Option<(f64, Vec<String>)> = None; // (distance from target, map)
+        let (mut low, mut high) = (0.0f64, 1.0f64);
+        for _ in 0..TARGET_ATTEMPTS {
+            let branching = (low + high) / 2.0;
+            let lines = self.generate_with_branching(branching);
+            let map = Map::from_lines(&lines)?;
+            let (score, rating) = map.get_overall_trailhead_score_and_rating();
+            let trailheads = map.trailheads.len().max(1) as f64;
+            let (actual, wanted) = match target {
+                Target::Score(wanted) => (score as f64 / trailheads, wanted),
+                Target::Rating(wanted) => (rating as f64 / trailheads, wanted),
+            };
+
+            if best.as_ref().is_none_or(|(distance, _)| (actual - wanted).abs() < *distance) {
+                best = Some(((actual - wanted).abs(), lines));
+            }
+            if actual < wanted { low = branching } else { high = branching }
+        }
+
+        Ok(best.unwrap().1)
+    }
+
+    // Plants the trailheads, then grows a trail up from each one a step at a time, forking into a
+    // second direction with the given chance. Trails take over the random background as they go and
+    // merge into each other where they meet at the right elevation.
+    fn generate_with_branching(&self, branching: f64) -> Vec<String> {
+        let mut rng = Rng::new(self.seed);
+        let cells = self.width * self.height;
+
+        // Random background, without any zeroes so that the only trailheads are the planted ones
+        let mut elevations: Vec<u8> = (0..cells).map(|_| 1 + rng.below(9) as u8).collect();
+        let mut fixed: Vec<bool> = vec![false; cells];
+
+        let trailhead_count = ((cells as f64 * self.trailhead_density).round() as usize).min(cells);
+        let mut order: Vec<usize> = (0..cells).collect();
+        for i in 0..trailhead_count {
+            order.swap(i, i + rng.below(cells - i));
+        }
+        for &cell in order[..trailhead_count].iter() {
+            elevations[cell] = 0;
+            fixed[cell] = true;
+        }
+
+        for &trailhead in order[..trailhead_count].iter() {
+            let mut frontier: Vec<usize> = vec![trailhead];
+            while let Some(cell) = frontier.pop() {
+                let elevation = elevations[cell];
+                if elevation == 9 {
+                    continue;
+                }
+
+                let (row, col) = (cell / self.width, cell % self.width);
+                let mut neighbours: Vec<usize> = [
+                    (row > 0).then(|| cell - self.width),
+                    (col > 0).then(|| cell - 1),
+                    (row + 1 < self.height).then(|| cell + self.width),
+                    (col + 1 < self.width).then(|| cell + 1),
+                ].into_iter().flatten()
+                    .filter(|&next| !fixed[next] || elevations[next] == elevation + 1)
+                    .collect();
+
+                let forks = if rng.chance(branching) { 2 } else { 1 };
+                for _ in 0..forks.min(neighbours.len()) {
+                    let next = neighbours.swap_remove(rng.below(neighbours.len()));
+                    if !fixed[next] {
+                        elevations[next] = elevation + 1;
+                        fixed[next] = true;
+                        frontier.push(next);
+                    }
+                }
+            }
+        }
+
+        elevations.chunks(self.width.max(1)).map(|row| {
+            row.iter().map(|&elevation| char::from_digit(elevation as u32, 10).unwrap()).collect()
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(width: usize, height: usize, target: Option<Target>, seed: u64) -> MapSpec {
+        MapSpec{width, height, trailhead_density: 0.1, target, seed}
+    }
+
+    #[test]
+    fn maps_only_depend_on_the_seed() {
+        let lines = spec(30, 20, None, 5).generate().unwrap();
+        assert_eq!(lines, spec(30, 20, None, 5).generate().unwrap());
+        assert_ne!(lines, spec(30, 20, None, 6).generate().unwrap());
+
+        assert_eq!(lines.len(), 20);
+        assert!(lines.iter().all(|line| line.len() == 30));
+        let trailheads: usize = lines.iter().map(|line| line.matches('0').count()).sum();
+        assert_eq!(trailheads, 60);
+    }
+
+    #[test]
+    fn single_cell_maps() {
+        assert_eq!(spec(1, 1, None, 0).generate().unwrap().len(), 1);
+        let map = MapSpec{width: 1, height: 1, trailhead_density: 1.0, target: None, seed: 0};
+        assert_eq!(map.generate().unwrap(), vec!["0".to_owned()]);
+    }
+
+    #[test]
+    fn targets_pull_the_mean_towards_them() {
+        let mean_rating = |target: f64| {
+            let map = Map::from_lines(&spec(40, 40, Some(Target::Rating(target)), 3).generate().unwrap()).unwrap();
+            map.get_overall_trailhead_score_and_rating().1 as f64 / map.trailheads.len() as f64
+        };
+        assert!(mean_rating(1.0) < mean_rating(20.0));
+    }
+}
@@ -1,17 +1,135 @@
 use util::res::Result;
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Instant;
+use clap::Parser;
+use generator::{MapSpec, Rng, Target};
+
+mod counting;
+mod generator;
+mod trails;
+
+#[derive(Parser, Debug)]
+struct Args {
+    #[arg(short, long, required_unless_present_any = ["generate", "check"])]
+    file: Option<PathBuf>,
+
+    // How much higher each step of a trail may climb (e.g. `1,2` also allows skipping an elevation)
+    #[arg(long, value_delimiter = ',', default_value = "1")]
+    steps: Vec<u8>,
+
+    // Elevation at which trails end
+    #[arg(long, default_value_t = 9)]
+    summit: u8,
+
+    // Score and rating of every trailhead
+    #[arg(long)]
+    trailheads: bool,
+
+    // How many trailheads and trails reach every summit
+    #[arg(long)]
+    summits: bool,
+
+    // List the trails from this trailhead (`row,col`)
+    #[arg(long, value_parser = parse_coord)]
+    from: Option<(usize, usize)>,
+
+    // Only the trails from --from that end at this summit (`row,col`)
+    #[arg(long, value_parser = parse_coord, requires = "from")]
+    to: Option<(usize, usize)>,
+
+    // Most trails to list with --from
+    #[arg(long, default_value_t = 10)]
+    limit: usize,
+
+    // Shortest and longest trail on the map
+    #[arg(long)]
+    extremes: bool,
+
+    // Time the per-trailhead DFS against counting every cell at once, on the input and on a generated
+    // map this many cells square
+    #[arg(long, num_args = 0..=1, default_missing_value = "2000")]
+    bench: Option<usize>,
+
+    // Print a generated map of this size (`WIDTHxHEIGHT`) instead of reading one
+    #[arg(long, value_parser = parse_size)]
+    generate: Option<(usize, usize)>,
+
+    // Share of a generated map's cells that are trailheads
+    #[arg(long, default_value_t = 0.1)]
+    density: f64,
+
+    // Mean score per trailhead for a generated map to aim for
+    #[arg(long, conflicts_with = "target_rating")]
+    target_score: Option<f64>,
+
+    // Mean rating per trailhead for a generated map to aim for
+    #[arg(long)]
+    target_rating: Option<f64>,
+
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    // Check the DFS against counting by elevation, trailhead by trailhead, on this many small generated
+    // maps with random trail rules
+    #[arg(long)]
+    check: Option<usize>,
+}
+
+fn parse_coord(s: &str) -> std::result::Result<(usize, usize), String> {
+    let (row, col) = s.split_once(',').ok_or_else(|| format!("Expected row,col: {}", s))?;
+    let parse = |n: &str| n.trim().parse::<usize>().map_err(|_| format!("Invalid coordinate: {}", s));
+
+    Ok((parse(row)?, parse(col)?))
+}
+
+fn parse_size(s: &str) -> std::result::Result<(usize, usize), String> {
+    let (width, height) = s.split_once('x').ok_or_else(|| format!("Expected WIDTHxHEIGHT: {}", s))?;
+    let parse = |n: &str| n.trim().parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(|| format!("Invalid size: {}", s));
+
+    Ok((parse(width)?, parse(height)?))
+}
+
+// The trails that count: every step climbs by one of `steps`, ending at the `summit` elevation
+#[derive(Clone)]
+struct TrailRules {
+    steps: Vec<u8>,
+    summit: u8,
+}
+
+impl TrailRules {
+    // Steps have to climb, or trails could go around in circles forever, and can't climb more than
+    // the 9 elevations there are
+    fn new(steps: Vec<u8>, summit: u8) -> Result<TrailRules> {
+        if steps.is_empty() || steps.iter().any(|step| !(1..=9).contains(step)) {
+            return Err(From::from(format!("Invalid steps: {:?}", steps)));
+        }
+
+        Ok(TrailRules{steps, summit})
+    }
+}
+
+impl Default for TrailRules {
+    fn default() -> TrailRules {
+        TrailRules{steps: vec![1], summit: 9}
+    }
+}
+
+// Every cell of a trail in order, trailhead first
+type Trail = Vec<(usize, usize)>;
 
 struct Map {
     width: usize,
     height: usize,
     elevations: Vec<Vec<u8>>,
     trailheads: Vec<(usize, usize)>,
+    rules: TrailRules,
 }
 
 impl Map {
-    fn from_lines(lines: &Vec<String>) -> Result<Map> {
+    fn from_lines(lines: &[String]) -> Result<Map> {
         let height = lines.len();
-        let width = lines[0].len();
+        let width = lines.first().map_or(0, |line| line.len());
         let mut trailheads: Vec<(usize, usize)> = vec![];
         let elevations: Vec<Vec<u8>> = lines.iter().enumerate().map(|(row, line)| {
             line.chars().enumerate().map(|(col, c)| {
@@ -26,59 +144,246 @@ impl Map {
             }).collect::<Result<Vec<u8>>>()
         }).collect::<Result<Vec<Vec<u8>>>>()?;
 
-        Ok(Map{width, height, elevations, trailheads})
+        Ok(Map{width, height, elevations, trailheads, rules: TrailRules::default()})
     }
 
-    fn get_trailhead_score_and_rating(&self, trailhead_coords: (usize, usize)) -> (usize, usize) {
-        let mut dfs_stack: Vec<(u8, (usize, usize))> = vec![(0, trailhead_coords)];
-        let mut reached_summits: HashSet<(usize, usize)> = HashSet::new();
-        let mut rating: usize = 0;
-
-        while !dfs_stack.is_empty() {
-            let (desired_elevation, coord) = dfs_stack.pop().unwrap();
-            let curr_elevation = self.elevations[coord.0][coord.1];
-            if curr_elevation != desired_elevation { continue }
-
-            if desired_elevation == 9 {
-                rating += 1;
-                reached_summits.insert(coord);
-                continue;
-            }
+    fn to_lines(&self) -> Vec<String> {
+        self.elevations.iter().map(|line| {
+            line.iter().map(|&elevation| char::from_digit(elevation as u32, 10).unwrap()).collect()
+        }).collect()
+    }
 
-            // Up state
-            if coord.0 > 0 {
-                dfs_stack.push((desired_elevation + 1, (coord.0 - 1, coord.1)));
-            }
+    fn is_summit(&self, coord: (usize, usize)) -> bool {
+        self.elevations[coord.0][coord.1] == self.rules.summit
+    }
 
-            // Left state
-            if coord.1 > 0 {
-                dfs_stack.push((desired_elevation + 1, (coord.0, coord.1 - 1)));
-            }
+    // Cells that a trail at `coord` can continue onto, up, left, down then right
+    fn next_steps(&self, coord: (usize, usize)) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let curr_elevation = self.elevations[coord.0][coord.1];
+        let candidates = [
+            (coord.0 > 0).then(|| (coord.0 - 1, coord.1)),
+            (coord.1 > 0).then(|| (coord.0, coord.1 - 1)),
+            (coord.0 < self.height - 1).then(|| (coord.0 + 1, coord.1)),
+            (coord.1 < self.width - 1).then(|| (coord.0, coord.1 + 1)),
+        ];
 
-            // Down state
-            if coord.0 < self.height - 1 {
-                dfs_stack.push((desired_elevation + 1, (coord.0 + 1, coord.1)));
-            }
+        candidates.into_iter().flatten().filter(move |&(row, col)| {
+            let elevation = self.elevations[row][col];
+            curr_elevation < self.rules.summit
+                && elevation <= self.rules.summit
+                && elevation.checked_sub(curr_elevation).is_some_and(|climb| self.rules.steps.contains(&climb))
+        })
+    }
 
-            // Right
-            if coord.1 < self.width - 1 {
-                dfs_stack.push((desired_elevation + 1, (coord.0, coord.1 + 1)));
+    // Number of distinct trails from the trailhead to each summit it reaches
+    fn get_summit_ratings(&self, trailhead_coords: (usize, usize)) -> HashMap<(usize, usize), usize> {
+        let mut dfs_stack: Vec<(usize, usize)> = vec![trailhead_coords];
+        let mut ratings: HashMap<(usize, usize), usize> = HashMap::new();
+
+        while let Some(coord) = dfs_stack.pop() {
+            if self.is_summit(coord) {
+                *ratings.entry(coord).or_default() += 1;
+                continue;
             }
+
+            dfs_stack.extend(self.next_steps(coord));
         }
 
-        (reached_summits.len(), rating)
+        ratings
     }
 
-    fn get_overall_trailhead_score_and_rating(&self) -> (usize, usize) {
+    fn get_trailhead_score_and_rating(&self, trailhead_coords: (usize, usize)) -> (usize, usize) {
+        let ratings = self.get_summit_ratings(trailhead_coords);
+
+        (ratings.len(), ratings.values().sum())
+    }
+
+    // Re-explores the trails shared between trailheads, see get_overall_trailhead_score_and_rating
+    fn get_overall_trailhead_score_and_rating_dfs(&self) -> (usize, usize) {
         self.trailheads.iter().map(|trailhead_coords| self.get_trailhead_score_and_rating(*trailhead_coords))
             .fold((0, 0), |acc, (score, rating)| (acc.0 + score, acc.1 + rating))
     }
+
+    fn get_overall_trailhead_score_and_rating(&self) -> (usize, usize) {
+        let counts = self.get_all_scores_and_ratings();
+        self.trailheads.iter()
+            .fold((0, 0), |acc, &(row, col)| (acc.0 + counts.scores[row][col], acc.1 + counts.ratings[row][col]))
+    }
+}
+
+// Diagonal ridges running from elevation 0 up to 9 and back down, so that every trailhead has
+// hundreds of overlapping trails to choose from
+fn ridge_map(size: usize) -> Result<Map> {
+    let lines: Vec<String> = (0..size).map(|row| {
+        (0..size).map(|col| {
+            let elevation = ((row + col) % 18).abs_diff(9) as u32;
+            char::from_digit(elevation, 10).unwrap()
+        }).collect()
+    }).collect();
+
+    Map::from_lines(&lines)
+}
+
+fn bench(name: &str, map: &Map) {
+    let start = Instant::now();
+    let dfs = map.get_overall_trailhead_score_and_rating_dfs();
+    let dfs_time = start.elapsed();
+
+    let start = Instant::now();
+    let counted = map.get_overall_trailhead_score_and_rating();
+    let counted_time = start.elapsed();
+
+    println!("{} ({} trailheads):", name, map.trailheads.len());
+    println!("  DFS per trailhead: score {}, rating {} in {:?}", dfs.0, dfs.1, dfs_time);
+    println!(
+        "  Counted by elevation: score {}, rating {} in {:?} ({:.1}x faster)",
+        counted.0, counted.1, counted_time, dfs_time.as_secs_f64() / counted_time.as_secs_f64()
+    );
+    if dfs != counted {
+        println!("  MISMATCH");
+    }
+}
+
+// Generates maps of random sizes, densities and trail rules, making sure each one reads back in as
+// exactly the map that was printed and that both ways of counting agree on every trailhead
+fn check(maps: usize, seed: u64) -> Result<()> {
+    let mut rng = Rng::new(seed);
+    let mut trailheads = 0usize;
+    let mut failures = 0usize;
+    for _ in 0..maps {
+        let spec = MapSpec{
+            width: 1 + rng.below(40),
+            height: 1 + rng.below(40),
+            trailhead_density: rng.below(30) as f64 / 100.0,
+            target: None,
+            seed: rng.next_u64(),
+        };
+        let steps: Vec<u8> = (1..=3).filter(|&step| step == 1 || rng.chance(0.3)).collect();
+        let summit = 1 + rng.below(9) as u8;
+        let describe = || format!(
+            "{}x{} map, density {}, seed {}, steps {:?}, summit {}",
+            spec.width, spec.height, spec.trailhead_density, spec.seed, steps, summit
+        );
+
+        let lines = spec.generate()?;
+        let mut map = Map::from_lines(&lines)?;
+        if map.to_lines() != lines {
+            println!("Round trip failed: {}", describe());
+            failures += 1;
+            continue;
+        }
+
+        map.rules = TrailRules{steps: steps.clone(), summit};
+        let counts = map.get_all_scores_and_ratings();
+        for &(row, col) in map.trailheads.iter() {
+            let dfs = map.get_trailhead_score_and_rating((row, col));
+            let counted = (counts.scores[row][col], counts.ratings[row][col]);
+            if dfs != counted {
+                println!("Trailhead ({}, {}) mismatch, DFS {:?} vs counted {:?}: {}", row, col, dfs, counted, describe());
+                failures += 1;
+            }
+        }
+        trailheads += map.trailheads.len();
+    }
+
+    println!("Checked {} maps with {} trailheads: {} failures", maps, trailheads, failures);
+    if failures > 0 {
+        return Err(From::from(format!("{} failures", failures)));
+    }
+
+    Ok(())
+}
+
+fn format_trail(trail: &Trail) -> String {
+    trail.iter().map(|(row, col)| format!("({}, {})", row, col)).collect::<Vec<String>>().join(" -> ")
+}
+
+fn print_trails(map: &Map, trailhead: (usize, usize), summit: Option<(usize, usize)>, limit: usize) -> Result<()> {
+    if !map.trailheads.contains(&trailhead) {
+        return Err(From::from(format!("Not a trailhead: {:?}", trailhead)));
+    }
+
+    let total = match summit {
+        Some(summit) => map.count_trails_between(trailhead, summit),
+        None => map.get_trailhead_score_and_rating(trailhead).1,
+    };
+    let trails = map.trails_from(trailhead, summit, limit);
+    println!("{} trails from {:?}, showing {}:", total, trailhead, trails.len());
+    for trail in trails.iter() {
+        println!("  {}", format_trail(trail));
+    }
+
+    Ok(())
 }
 
 fn main() -> Result<()> {
-    let file_path = util::file::get_input_file_path();
-    let lines = util::file::read_lines_raw(file_path)?;
-    let map = Map::from_lines(&lines)?;
+    let args = Args::parse();
+    if !(0.0..=1.0).contains(&args.density) {
+        return Err(From::from(format!("Invalid density: {}", args.density)));
+    }
+
+    // Only the map goes to stdout, so that it can be redirected straight into a file
+    if let Some((width, height)) = args.generate {
+        let target = match (args.target_score, args.target_rating) {
+            (Some(score), _) => Some(Target::Score(score)),
+            (_, Some(rating)) => Some(Target::Rating(rating)),
+            (None, None) => None,
+        };
+        let spec = MapSpec{width, height, trailhead_density: args.density, target, seed: args.seed};
+        for line in spec.generate()? {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
+    if let Some(maps) = args.check {
+        return check(maps, args.seed);
+    }
+
+    let lines = match args.file {
+        Some(file_path) => util::file::read_lines_raw(file_path)?,
+        None => vec![],
+    };
+    let mut map = Map::from_lines(&lines)?;
+    map.rules = TrailRules::new(args.steps, args.summit)?;
+
+    if let Some(size) = args.bench {
+        bench("Input", &map);
+        let mut ridges = ridge_map(size)?;
+        ridges.rules = map.rules.clone();
+        bench(&format!("Ridges {}x{}", size, size), &ridges);
+        let spec = MapSpec{width: size, height: size, trailhead_density: args.density, target: None, seed: args.seed};
+        let mut generated = Map::from_lines(&spec.generate()?)?;
+        generated.rules = map.rules.clone();
+        bench(&format!("Generated {}x{}", size, size), &generated);
+        return Ok(());
+    }
+
+    if args.trailheads {
+        for stats in map.trailhead_breakdown() {
+            println!("Trailhead {:?}: score {}, rating {}", stats.trailhead, stats.score, stats.rating);
+        }
+    }
+
+    if args.summits {
+        for stats in map.summit_breakdown() {
+            println!("Summit {:?}: reached from {} trailheads by {} trails", stats.summit, stats.trailheads, stats.rating);
+        }
+    }
+
+    if let Some(trailhead) = args.from {
+        print_trails(&map, trailhead, args.to, args.limit)?;
+    }
+
+    if args.extremes {
+        match map.shortest_and_longest_trails() {
+            Some((shortest, longest)) => {
+                println!("Shortest trail ({} cells): {}", shortest.len(), format_trail(&shortest));
+                println!("Longest trail ({} cells): {}", longest.len(), format_trail(&longest));
+            },
+            None => println!("No trails"),
+        }
+    }
 
     let (overall_trailhead_score, overall_trailhead_rating) = map.get_overall_trailhead_score_and_rating();
 
@@ -86,4 +391,81 @@ fn main() -> Result<()> {
     println!("Overall trailhead rating: {}", overall_trailhead_rating);
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "89010123
+78121874
+87430965
+96549874
+45678903
+32019012
+01329801
+10456732";
+
+    fn example_map() -> Map {
+        Map::from_lines(&EXAMPLE.lines().map(|line| line.to_owned()).collect::<Vec<String>>()).unwrap()
+    }
+
+    #[test]
+    fn example_score_and_rating() {
+        assert_eq!(example_map().get_overall_trailhead_score_and_rating(), (36, 81));
+    }
+
+    #[test]
+    fn steps_must_climb_by_one_to_nine() {
+        assert!(TrailRules::new(vec![1, 2], 9).is_ok());
+        assert!(TrailRules::new(vec![9], 9).is_ok());
+        assert!(TrailRules::new(vec![], 9).is_err());
+        assert!(TrailRules::new(vec![0], 9).is_err());
+        assert!(TrailRules::new(vec![1, 10], 9).is_err());
+        assert!(TrailRules::new(vec![1, 255], 9).is_err());
+    }
+
+    #[test]
+    fn trails_never_step_down() {
+        // Steps that would wrap around to going downhill if elevations were subtracted unchecked
+        let mut map = example_map();
+        map.rules = TrailRules{steps: vec![1, 255, 254], summit: 9};
+        for row in 0..map.height {
+            for col in 0..map.width {
+                for (next_row, next_col) in map.next_steps((row, col)) {
+                    assert_eq!(map.elevations[next_row][next_col], map.elevations[row][col] + 1);
+                }
+            }
+        }
+        assert_eq!(map.get_overall_trailhead_score_and_rating(), (36, 81));
+
+        let ratings: usize = map.trailheads.iter().map(|&trailhead| map.get_summit_ratings(trailhead).values().sum::<usize>()).sum();
+        assert_eq!(ratings, 81);
+    }
+
+    #[test]
+    fn coords_parse_as_row_then_column() {
+        assert_eq!(parse_coord("3,4"), Ok((3, 4)));
+        assert_eq!(parse_coord(" 3 , 4 "), Ok((3, 4)));
+        assert!(parse_coord("3").is_err());
+        assert!(parse_coord("3,-4").is_err());
+        assert!(parse_coord("3,4,5").is_err());
+    }
+
+    #[test]
+    fn sizes_parse_as_width_by_height() {
+        assert_eq!(parse_size("40x30"), Ok((40, 30)));
+        assert_eq!(parse_size(" 40 x 30 "), Ok((40, 30)));
+        assert!(parse_size("40").is_err());
+        assert!(parse_size("40,30").is_err());
+        assert!(parse_size("0x30").is_err());
+        assert!(parse_size("40x0").is_err());
+        assert!(parse_size("40x-1").is_err());
+        assert!(parse_size("40x30x2").is_err());
+    }
+
+    #[test]
+    fn generated_maps_agree_with_dfs() {
+        assert!(check(30, 7).is_ok());
+    }
+}
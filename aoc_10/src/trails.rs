@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+use super::{Map, Trail};
+
+pub struct TrailheadStats {
+    pub trailhead: (usize, usize),
+    pub score: usize,
+    pub rating: usize,
+}
+
+pub struct SummitStats {
+    pub summit: (usize, usize),
+    pub trailheads: usize, // How many trailheads can reach this summit
+    pub rating: usize, // How many distinct trails end here, from any trailhead
+}
+
+impl Map {
+    pub fn trailhead_breakdown(&self) -> Vec<TrailheadStats> {
+        let counts = self.get_all_scores_and_ratings();
+        self.trailheads.iter().map(|&(row, col)| {
+            TrailheadStats{trailhead: (row, col), score: counts.scores[row][col], rating: counts.ratings[row][col]}
+        }).collect()
+    }
+
+    // Only summits reachable from at least one trailhead, top to bottom then left to right
+    pub fn summit_breakdown(&self) -> Vec<SummitStats> {
+        let mut by_summit: BTreeMap<(usize, usize), (usize, usize)> = BTreeMap::new();
+        for &trailhead in self.trailheads.iter() {
+            for (summit, rating) in self.get_summit_ratings(trailhead) {
+                let entry = by_summit.entry(summit).or_default();
+                entry.0 += 1;
+                entry.1 += rating;
+            }
+        }
+
+        by_summit.into_iter().map(|(summit, (trailheads, rating))| SummitStats{summit, trailheads, rating}).collect()
+    }
+
+    pub fn count_trails_between(&self, trailhead: (usize, usize), summit: (usize, usize)) -> usize {
+        self.get_summit_ratings(trailhead).get(&summit).copied().unwrap_or(0)
+    }
+
+    // The first `limit` trails from the trailhead (optionally only those ending at `summit`), in the
+    // order they're found going up, left, down then right from each cell
+    pub fn trails_from(&self, trailhead: (usize, usize), summit: Option<(usize, usize)>, limit: usize) -> Vec<Trail> {
+        let mut ret: Vec<Trail> = vec![];
+        let mut trail: Trail = vec![trailhead];
+        self.extend_trail(&mut trail, summit, limit, &mut ret);
+
+        ret
+    }
+
+    fn extend_trail(&self, trail: &mut Trail, summit: Option<(usize, usize)>, limit: usize, out: &mut Vec<Trail>) {
+        let coord = *trail.last().unwrap();
+        if self.is_summit(coord) {
+            if summit.is_none_or(|summit| summit == coord) {
+                out.push(trail.clone());
+            }
+            return;
+        }
+
+        for next in self.next_steps(coord) {
+            if out.len() >= limit {
+                return;
+            }
+
+            trail.push(next);
+            self.extend_trail(trail, summit, limit, out);
+            trail.pop();
+        }
+    }
+
+    // With a single step size every trail is the same length, so this only gets interesting when
+    // trails can skip elevations. Ties go to the first trailhead in reading order.
+    pub fn shortest_and_longest_trails(&self) -> Option<(Trail, Trail)> {
+        // (fewest, most) cells on the trails from each cell to a summit, working down from the summit
+        let mut lengths: Vec<Vec<Option<(usize, usize)>>> = vec![vec![None; self.width]; self.height];
+        let mut cells: Vec<(usize, usize)> = (0..self.height).flat_map(|row| (0..self.width).map(move |col| (row, col))).collect();
+        cells.sort_by_key(|&(row, col)| std::cmp::Reverse(self.elevations[row][col]));
+        for coord in cells {
+            lengths[coord.0][coord.1] = if self.is_summit(coord) {
+                Some((1, 1))
+            } else {
+                self.next_steps(coord).filter_map(|(row, col)| lengths[row][col]).fold(None, |acc, (fewest, most)| {
+                    let (acc_fewest, acc_most) = acc.unwrap_or((usize::MAX, 0));
+                    Some((acc_fewest.min(fewest + 1), acc_most.max(most + 1)))
+                })
+            };
+        }
+
+        let length_of = |coord: (usize, usize), longest: bool| {
+            lengths[coord.0][coord.1].map(|(fewest, most)| if longest { most } else { fewest })
+        };
+
+        // Follow any next step that's exactly one cell closer to the trail's length
+        let follow = |trailhead: (usize, usize), longest: bool| {
+            let mut trail: Trail = vec![trailhead];
+            let mut coord = trailhead;
+            while !self.is_summit(coord) {
+                let remaining = length_of(coord, longest).unwrap();
+                coord = self.next_steps(coord).find(|&next| length_of(next, longest) == Some(remaining - 1)).unwrap();
+                trail.push(coord);
+            }
+
+            trail
+        };
+
+        let mut trailheads: Vec<(usize, usize)> = self.trailheads.iter().copied()
+            .filter(|&trailhead| lengths[trailhead.0][trailhead.1].is_some())
+            .collect();
+        trailheads.sort();
+        let shortest = trailheads.iter().copied().min_by_key(|&trailhead| length_of(trailhead, false))?;
+        let longest = trailheads.iter().copied().rev().max_by_key(|&trailhead| length_of(trailhead, true))?;
+
+        Some((follow(shortest, false), follow(longest, true)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TrailRules;
+
+    const EXAMPLE: &str = "89010123
+78121874
+87430965
+96549874
+45678903
+32019012
+01329801
+10456732";
+
+    fn map(text: &str) -> Map {
+        Map::from_lines(&text.lines().map(|line| line.to_owned()).collect::<Vec<String>>()).unwrap()
+    }
+
+    #[test]
+    fn example_breakdowns() {
+        let map = map(EXAMPLE);
+        let trailheads = map.trailhead_breakdown();
+        assert_eq!(trailheads.iter().map(|stats| stats.score).collect::<Vec<usize>>(), vec![5, 6, 5, 3, 1, 3, 5, 3, 5]);
+        assert_eq!(trailheads.iter().map(|stats| stats.rating).collect::<Vec<usize>>(), vec![20, 24, 10, 4, 1, 4, 5, 8, 5]);
+
+        let summits = map.summit_breakdown();
+        assert_eq!(summits.iter().map(|stats| stats.trailheads).sum::<usize>(), 36);
+        assert_eq!(summits.iter().map(|stats| stats.rating).sum::<usize>(), 81);
+        assert!(summits.windows(2).all(|pair| pair[0].summit < pair[1].summit));
+
+        for stats in trailheads.iter() {
+            let between: usize = summits.iter().map(|summit| map.count_trails_between(stats.trailhead, summit.summit)).sum();
+            assert_eq!(between, stats.rating);
+        }
+        assert_eq!(map.count_trails_between((0, 2), (0, 0)), 0);
+    }
+
+    #[test]
+    fn trails_climb_to_the_summit() {
+        let map = map(EXAMPLE);
+        let trailhead = (0, 2);
+        let trails = map.trails_from(trailhead, None, usize::MAX);
+        assert_eq!(trails.len(), 20);
+        for trail in trails.iter() {
+            let elevations: Vec<u8> = trail.iter().map(|&(row, col)| map.elevations[row][col]).collect();
+            assert_eq!(elevations, (0..=9).collect::<Vec<u8>>());
+        }
+
+        assert_eq!(map.trails_from(trailhead, None, 3), trails[..3].to_vec());
+        let summit = *trails[0].last().unwrap();
+        let to_summit = map.trails_from(trailhead, Some(summit), usize::MAX);
+        assert_eq!(to_summit.len(), map.count_trails_between(trailhead, summit));
+        assert!(to_summit.iter().all(|trail| trail.last() == Some(&summit)));
+    }
+
+    #[test]
+    fn shortest_and_longest_skip_elevations() {
+        let mut map = map("01234\n23499");
+        map.rules = TrailRules{steps: vec![1], summit: 4};
+        let (shortest, longest) = map.shortest_and_longest_trails().unwrap();
+        assert_eq!((shortest.len(), longest.len()), (5, 5));
+
+        // Climbing by 2 onto the bottom row cuts a cell off
+        map.rules = TrailRules{steps: vec![1, 2], summit: 4};
+        let (shortest, longest) = map.shortest_and_longest_trails().unwrap();
+        assert_eq!((shortest.len(), longest.len()), (4, 5));
+        for trail in [shortest, longest] {
+            assert_eq!(map.elevations[trail[0].0][trail[0].1], 0);
+            assert!(map.is_summit(*trail.last().unwrap()));
+            assert!(trail.windows(2).all(|pair| map.next_steps(pair[0]).any(|next| next == pair[1])));
+        }
+
+        assert!(self::map("9876").shortest_and_longest_trails().is_none());
+    }
+}
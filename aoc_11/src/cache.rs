@@ -0,0 +1,136 @@
+use std::collections::{HashMap, HashSet};
+use super::number::Number;
+use super::rules::{RuleSet, Stone};
+
+// How many stones each stone turns into after some number of blinks, kept between queries so that
+// later ones (other starting stones, more blinks) reuse everything worked out for earlier ones
+pub struct ExpansionCache<'a> {
+    rules: &'a RuleSet,
+    blinked: HashMap<Stone, Vec<Stone>>, // What each stone becomes after one blink
+    counts: HashMap<(Stone, u64), Number>, // (stone, remaining blinks) -> number of stones
+}
+
+// The distinct stones after each blink, up to the blink after which no new values turn up
+pub struct DistinctStones {
+    pub per_blink: Vec<usize>, // Distinct values after 0, 1, 2, ... blinks
+    pub seen: Vec<usize>, // Distinct values after any of blinks 0 to n
+    pub closed_after: Option<u64>, // First blink to turn up no new values, if within the limit
+}
+
+impl<'a> ExpansionCache<'a> {
+    pub fn new(rules: &'a RuleSet) -> ExpansionCache<'a> {
+        ExpansionCache{rules, blinked: HashMap::new(), counts: HashMap::new()}
+    }
+
+    pub fn entries(&self) -> usize {
+        self.counts.len()
+    }
+
+    fn blink(&mut self, stone: &Stone) -> &[Stone] {
+        self.blinked.entry(stone.clone()).or_insert_with(|| self.rules.blink(stone))
+    }
+
+    // Fills in the counts depth first with an explicit stack, since queries can be far deeper than
+    // the call stack
+    pub fn count(&mut self, stone: &Stone, blinks: u64) -> Number {
+        if blinks == 0 {
+            return Number::from(1);
+        }
+
+        let mut stack: Vec<(Stone, u64, bool)> = vec![(stone.clone(), blinks, false)]; // (stone, blinks, children counted)
+        while let Some((stone, blinks, children_counted)) = stack.pop() {
+            if self.counts.contains_key(&(stone.clone(), blinks)) {
+                continue;
+            }
+
+            let new_stones = self.blink(&stone).to_vec();
+            if children_counted {
+                let total: Number = new_stones.into_iter().map(|new_stone| {
+                    if blinks == 1 { Number::from(1) } else { self.counts[&(new_stone, blinks - 1)].clone() }
+                }).sum();
+                self.counts.insert((stone, blinks), total);
+            } else {
+                stack.push((stone, blinks, true));
+                if blinks > 1 {
+                    stack.extend(new_stones.into_iter().map(|new_stone| (new_stone, blinks - 1, false)));
+                }
+            }
+        }
+
+        self.counts[&(stone.clone(), blinks)].clone()
+    }
+
+    pub fn count_all(&mut self, stones: &[Stone], blinks: u64) -> Number {
+        stones.iter().map(|stone| self.count(stone, blinks)).sum()
+    }
+
+    // Once a blink turns up nothing new, every later blink only turns up values that came from
+    // values already seen, so the set of values seen is closed from there on
+    pub fn distinct_stones(&mut self, stones: &[Stone], max_blinks: u64) -> DistinctStones {
+        let mut current: HashSet<Stone> = stones.iter().cloned().collect();
+        let mut seen: HashSet<Stone> = current.clone();
+        let mut ret = DistinctStones{per_blink: vec![current.len()], seen: vec![seen.len()], closed_after: None};
+
+        for blink in 1..=max_blinks {
+            let mut next: HashSet<Stone> = HashSet::new();
+            for stone in current.iter() {
+                next.extend(self.blink(stone).iter().cloned());
+            }
+
+            let seen_before = seen.len();
+            seen.extend(next.iter().cloned());
+            ret.per_blink.push(next.len());
+            ret.seen.push(seen.len());
+            current = next;
+
+            if seen.len() == seen_before {
+                ret.closed_after = Some(blink);
+                break;
+            }
+        }
+
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_share_expansions() {
+        let rules = RuleSet::parse(crate::rules::PUZZLE_RULES).unwrap();
+        let mut cache = ExpansionCache::new(&rules);
+        assert_eq!(cache.count(&Stone::from(0), 0), Number::from(1));
+        assert_eq!(cache.entries(), 0);
+
+        // 0 -> 1 -> 2024 -> 20 24 -> 2 0 2 4
+        assert_eq!(cache.count(&Stone::from(0), 4), Number::from(4));
+        let entries = cache.entries();
+        assert_eq!(cache.count(&Stone::from(1), 3), Number::from(4));
+        assert_eq!(cache.entries(), entries);
+
+        assert_eq!(cache.count_all(&[Stone::from(125), Stone::from(17)], 25), Number::from(55312));
+    }
+
+    #[test]
+    fn deep_queries_stay_off_the_call_stack() {
+        let rules = RuleSet::parse("eq 1 -> replace 2\nany -> replace 1").unwrap();
+        let mut cache = ExpansionCache::new(&rules);
+        assert_eq!(cache.count(&Stone::from(1), 200_000), Number::from(1));
+    }
+
+    #[test]
+    fn distinct_stones_until_no_new_values() {
+        let rules = RuleSet::parse(crate::rules::PUZZLE_RULES).unwrap();
+        let mut cache = ExpansionCache::new(&rules);
+        // 125 17 -> 253000 1 7 -> 253 0 2024 14168
+        let distinct = cache.distinct_stones(&[Stone::from(125), Stone::from(17)], 2);
+        assert_eq!((distinct.per_blink, distinct.seen, distinct.closed_after), (vec![2, 3, 4], vec![2, 5, 9], None));
+
+        let rules = RuleSet::parse("any -> replace 7").unwrap();
+        let mut cache = ExpansionCache::new(&rules);
+        let distinct = cache.distinct_stones(&[Stone::from(1), Stone::from(2)], 10);
+        assert_eq!((distinct.per_blink, distinct.seen, distinct.closed_after), (vec![2, 1, 1], vec![2, 3, 3], Some(2)));
+    }
+}
@@ -1,50 +1,217 @@
 use util::res::Result;
 use std::collections::HashMap;
+use std::path::PathBuf;
+use clap::Parser;
+use rules::{RuleSet, Stone};
+use cache::ExpansionCache;
+use number::Number;
 
-fn blink_n(stones: &Vec<u64>, iterations: usize) -> usize {
-    let mut stone_counts: HashMap<u64, usize> = stones.iter().fold(HashMap::new(), |mut acc, stone| {
-        *acc.entry(*stone).or_insert(0) += 1;
+mod cache;
+mod number;
+mod rules;
+
+#[derive(Parser, Debug)]
+struct Args {
+    #[arg(short, long, required_unless_present = "query")]
+    file: Option<PathBuf>,
+
+    // Blink with the rules in this file instead of the puzzle's (see rules::Rule for the format)
+    #[arg(short, long)]
+    rules: Option<PathBuf>,
+
+    // Blink this many times instead of the two puzzle parts
+    #[arg(short, long)]
+    blinks: Option<u64>,
+
+    // Count what each of these `stone:blinks` queries turns into, instead of the two puzzle parts
+    // (e.g. `0:75,125:25`)
+    #[arg(short, long, value_delimiter = ',', value_parser = parse_query, conflicts_with_all = ["blinks", "distinct"])]
+    query: Vec<(Stone, u64)>,
+
+    // Follow how many distinct stone values there are through up to this many blinks
+    #[arg(short, long)]
+    distinct: Option<u64>,
+}
+
+fn parse_stones(contents: &str) -> Result<Vec<Stone>> {
+    contents.split_whitespace().map(|raw_num| {
+        raw_num.parse::<Stone>().map_err(|e| e.into())
+    }).collect::<Result<Vec<Stone>>>()
+}
+
+fn parse_query(s: &str) -> std::result::Result<(Stone, u64), String> {
+    let (stone, blinks) = s.split_once(':').ok_or_else(|| format!("Expected stone:blinks: {}", s))?;
+    let stone = stone.trim().parse::<Stone>().map_err(|_| format!("Invalid stone: {}", s))?;
+    let blinks = blinks.trim().parse::<u64>().map_err(|_| format!("Invalid blink count: {}", s))?;
+
+    Ok((stone, blinks))
+}
+
+fn blink_once(stone_counts: &HashMap<Stone, Number>, rules: &RuleSet, blinked: &mut HashMap<Stone, Vec<Stone>>) -> HashMap<Stone, Number> {
+    let mut next_counts: HashMap<Stone, Number> = HashMap::new();
+    for (stone, count) in stone_counts.iter() {
+        let new_stones = blinked.entry(stone.clone()).or_insert_with(|| rules.blink(stone));
+        for new_stone in new_stones.iter() {
+            let new_count = next_counts.entry(new_stone.clone()).or_insert(Number::from(0));
+            *new_count = &*new_count + count;
+        }
+    }
+
+    next_counts
+}
+
+// Each distinct stone is only run through the rules once, however many times and blinks it turns up
+// in. Unlike ExpansionCache, this only ever keeps one blink's worth of counts (plus one saved copy),
+// so it can go on for any number of blinks.
+//
+// Once the counts repeat (stones that stop changing, or rules that cycle values round without
+// splitting them), the blinks left are skipped a whole period at a time. The saved copy is moved up
+// to the latest counts every time the distance to it reaches the next power of two (Brent's cycle
+// detection), so a repeat is spotted within a couple of periods of it starting. Counts that never
+// repeat, as with the puzzle's rules where the stones keep multiplying, still take a pass over every
+// distinct stone per blink, with the counts gaining digits as they go.
+fn blink_n(stones: &[Stone], rules: &RuleSet, iterations: u64) -> Number {
+    let mut stone_counts: HashMap<Stone, Number> = stones.iter().fold(HashMap::new(), |mut acc, stone| {
+        let count = acc.entry(stone.clone()).or_insert(Number::from(0));
+        *count = &*count + &Number::from(1);
         acc
     });
+    let mut blinked: HashMap<Stone, Vec<Stone>> = HashMap::new();
+    let (mut saved, mut saved_at, mut power) = (stone_counts.clone(), 0, 1);
+
+    let mut blink = 0;
+    while blink < iterations {
+        stone_counts = blink_once(&stone_counts, rules, &mut blinked);
+        blink += 1;
 
-    for _ in 0..iterations {
-        stone_counts = stone_counts.iter().fold(HashMap::new(), |mut acc, (stone, count)| {
-            let blinked = match (stone, stone.to_string().as_str()) {
-                (0, _) => vec![1],
-                (_, s) if (s.len() & 1) == 0 => {
-                    let (first, second) = s.split_at(s.len() >> 1);
-                    vec![first.parse::<u64>().unwrap(), second.parse::<u64>().unwrap()]
-                },
-                _ => vec![stone * 2024]
-            };
-
-            for new_stone in blinked {
-                *acc.entry(new_stone).or_insert(0) += count;
+        if stone_counts == saved {
+            let period = blink - saved_at;
+            for _ in 0..(iterations - blink) % period {
+                stone_counts = blink_once(&stone_counts, rules, &mut blinked);
             }
-            acc
-        });
+            break;
+        }
+
+        if blink - saved_at == power {
+            (saved, saved_at, power) = (stone_counts.clone(), blink, power * 2);
+        }
     }
 
-    stone_counts.iter().map(|(_, count)| count).sum()
+    stone_counts.into_values().sum()
 }
 
-fn part1(stones: &Vec<u64>) {
-    println!("After 25 blinks, there are {} stones", blink_n(stones, 25));
+fn distinct(stones: &[Stone], cache: &mut ExpansionCache, max_blinks: u64) {
+    let distinct = cache.distinct_stones(stones, max_blinks);
+    for (blink, (values, seen)) in distinct.per_blink.iter().zip(distinct.seen.iter()).enumerate() {
+        println!("After {} blinks: {} distinct stones, {} seen so far", blink, values, seen);
+    }
+
+    match distinct.closed_after {
+        Some(blink) => println!("No new stone values after {} blinks", blink),
+        None => println!("Still finding new stone values after {} blinks", max_blinks),
+    }
 }
 
-fn part2(stones: &Vec<u64>) {
-    println!("After 75 blinks, there are {} stones", blink_n(stones, 75));
+fn part1(stones: &[Stone], cache: &mut ExpansionCache) {
+    println!("After 25 blinks, there are {} stones", cache.count_all(stones, 25));
+}
+
+fn part2(stones: &[Stone], cache: &mut ExpansionCache) {
+    println!("After 75 blinks, there are {} stones", cache.count_all(stones, 75));
 }
 
 fn main() -> Result<()> {
-    let file_path = util::file::get_input_file_path();
-    let contents = util::file::read_to_string(file_path)?;
-    let stones = contents.split_whitespace().map(|raw_num| {
-        raw_num.parse::<u64>().map_err(|e| e.into())
-    }).collect::<Result<Vec<u64>>>()?;
-
-    part1(&stones);
-    part2(&stones);
-    
+    let args = Args::parse();
+    let rules = match args.rules {
+        Some(rules_path) => RuleSet::parse(&util::file::read_to_string(rules_path)?)?,
+        None => RuleSet::parse(rules::PUZZLE_RULES)?,
+    };
+
+    // Queries bring their own stones, so they're the only thing that can do without a file
+    let mut cache = ExpansionCache::new(&rules);
+    if !args.query.is_empty() {
+        for (stone, blinks) in args.query.iter() {
+            println!("Stone {} after {} blinks: {} stones", stone, blinks, cache.count(stone, *blinks));
+        }
+        println!("{} cached expansions", cache.entries());
+        return Ok(());
+    }
+
+    let Some(file) = args.file else { return Err(From::from("No file of stones given")) };
+    let stones = parse_stones(&util::file::read_to_string(file)?)?;
+    if let Some(blinks) = args.blinks {
+        println!("After {} blinks, there are {} stones", blinks, blink_n(&stones, &rules, blinks));
+        return Ok(());
+    }
+
+    if let Some(max_blinks) = args.distinct {
+        distinct(&stones, &mut cache, max_blinks);
+        return Ok(());
+    }
+
+    part1(&stones, &mut cache);
+    part2(&stones, &mut cache);
+
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The stones from the puzzle's example
+    const EXAMPLE_STONES: &str = "125 17";
+
+    fn blink_rules(text: &str, stones: &str, blinks: u64) -> Number {
+        blink_n(&parse_stones(stones).unwrap(), &RuleSet::parse(text).unwrap(), blinks)
+    }
+
+    #[test]
+    fn example_counts() {
+        let stones = parse_stones(EXAMPLE_STONES).unwrap();
+        let rules = RuleSet::parse(rules::PUZZLE_RULES).unwrap();
+        assert_eq!(blink_n(&stones, &rules, 6), Number::from(22));
+        assert_eq!(blink_n(&stones, &rules, 25), Number::from(55312));
+
+        let mut cache = ExpansionCache::new(&rules);
+        assert_eq!(cache.count_all(&stones, 6), Number::from(22));
+        assert_eq!(cache.count_all(&stones, 25), Number::from(55312));
+        assert_eq!(cache.count_all(&stones, 75), blink_n(&stones, &rules, 75));
+    }
+
+    #[test]
+    fn skips_ahead_once_the_counts_repeat() {
+        // Settles on a single value
+        assert_eq!(blink_rules("any -> replace 7", "1 2 3", u64::MAX), Number::from(3));
+
+        // Cycles 1 -> 2 -> 3 -> 1 without ever splitting, after a few blinks that don't repeat
+        let cycle = "eq 1 -> replace 2\neq 2 -> replace 3\neq 3 -> replace 1\nany -> replace 1";
+        for blinks in [u64::MAX - 2, u64::MAX - 1, u64::MAX, 1_000_000_000_000] {
+            let skipped = blink_rules(cycle, "9 1 1 2", blinks);
+            let direct = blink_rules(cycle, "9 1 1 2", 30 + blinks % 3);
+            assert_eq!(skipped, direct, "{}", blinks);
+        }
+    }
+
+    #[test]
+    fn parses_stones_and_queries() {
+        assert_eq!(parse_stones(" 0  18446744073709551616\n").unwrap()[1].to_string(), "18446744073709551616");
+        assert!(parse_stones("1 -2").is_err());
+
+        assert_eq!(parse_query(" 125 : 25").unwrap(), (Number::from(125), 25));
+        assert!(parse_query("125").is_err());
+        assert!(parse_query("x:1").is_err());
+        assert!(parse_query("1:-1").is_err());
+    }
+
+    #[test]
+    fn file_is_only_optional_without_the_puzzle_parts() {
+        assert!(Args::try_parse_from(["aoc_11"]).is_err());
+        assert!(Args::try_parse_from(["aoc_11", "-b", "5"]).is_err());
+        assert!(Args::try_parse_from(["aoc_11", "-q", "0:75"]).is_ok());
+        assert!(Args::try_parse_from(["aoc_11", "-d", "10"]).is_err());
+        assert!(Args::try_parse_from(["aoc_11", "-q", "0:75", "-b", "5"]).is_err());
+        assert!(Args::try_parse_from(["aoc_11", "-f", "input", "-d", "10"]).is_ok());
+        assert!(Args::try_parse_from(["aoc_11", "-f", "input"]).is_ok());
+    }
+}
@@ -0,0 +1,201 @@
+use std::fmt;
+use std::ops::Add;
+use num_bigint::BigUint;
+use util::file::GenericParseError;
+
+// A stone value or stone count: a plain u64 for as long as it fits, switching over to an arbitrary
+// precision integer once it doesn't. Only values above u64::MAX are ever Big, so that each value has
+// exactly one representation and the derived Eq and Hash hold.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Number {
+    Small(u64),
+    Big(BigUint),
+}
+
+impl Number {
+    fn from_big(big: BigUint) -> Number {
+        match u64::try_from(&big) {
+            Ok(small) => Number::Small(small),
+            Err(_) => Number::Big(big),
+        }
+    }
+
+    fn to_big(&self) -> BigUint {
+        match self {
+            Number::Small(small) => BigUint::from(*small),
+            Number::Big(big) => big.clone(),
+        }
+    }
+
+    pub fn is_big(&self) -> bool {
+        matches!(self, Number::Big(_))
+    }
+
+    pub fn count_digits(&self) -> u32 {
+        match self {
+            Number::Small(small) => small.checked_ilog10().map_or(1, |log| log + 1),
+            Number::Big(big) => {
+                // A number with b bits is at least 2^(b - 1), so it has at least this many digits
+                // (less one), and since it's under 2^b it can only be a digit or so more
+                let mut log = ((big.bits() - 1) as f64 * std::f64::consts::LOG10_2) as u32;
+                while *big >= BigUint::from(10u32).pow(log + 1) {
+                    log += 1;
+                }
+                log + 1
+            },
+        }
+    }
+
+    pub fn is_multiple_of(&self, divisor: u64) -> bool {
+        match self {
+            Number::Small(small) => small.is_multiple_of(divisor),
+            Number::Big(big) => (big % divisor) == BigUint::ZERO,
+        }
+    }
+
+    pub fn mul(&self, factor: u64) -> Number {
+        match self {
+            Number::Small(small) => small.checked_mul(factor)
+                .map_or_else(|| Number::Big(BigUint::from(*small) * factor), Number::Small),
+            Number::Big(big) => Number::from_big(big * factor),
+        }
+    }
+
+    // The number's digits split into everything before the last `digits` digits, and those digits
+    pub fn split_digits(&self, digits: u32) -> (Number, Number) {
+        match self {
+            Number::Small(small) => match 10u64.checked_pow(digits) {
+                Some(divisor) => (Number::Small(small / divisor), Number::Small(small % divisor)),
+                // Past 10^19 a u64 doesn't have that many digits anyway
+                None => (Number::Small(0), Number::Small(*small)),
+            },
+            Number::Big(big) => {
+                let divisor = BigUint::from(10u32).pow(digits);
+                (Number::from_big(big / &divisor), Number::from_big(big % &divisor))
+            },
+        }
+    }
+}
+
+impl From<u64> for Number {
+    fn from(small: u64) -> Number {
+        Number::Small(small)
+    }
+}
+
+impl Add for &Number {
+    type Output = Number;
+
+    fn add(self, other: &Number) -> Number {
+        match (self, other) {
+            (Number::Small(a), Number::Small(b)) => a.checked_add(*b)
+                .map_or_else(|| Number::Big(BigUint::from(*a) + *b), Number::Small),
+            _ => Number::from_big(self.to_big() + other.to_big()),
+        }
+    }
+}
+
+impl std::iter::Sum<Number> for Number {
+    fn sum<I: Iterator<Item = Number>>(iter: I) -> Number {
+        iter.fold(Number::Small(0), |acc, n| &acc + &n)
+    }
+}
+
+impl std::str::FromStr for Number {
+    type Err = GenericParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit()) {
+            return Err(GenericParseError::ValueError(format!("Invalid number: {}", s)));
+        }
+
+        Ok(match s.parse::<u64>() {
+            Ok(small) => Number::Small(small),
+            Err(_) => Number::from_big(s.parse::<BigUint>().unwrap()),
+        })
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::Small(small) => write!(f, "{}", small),
+            Number::Big(big) => write!(f, "{}", big),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(s: &str) -> Number {
+        s.parse::<Number>().unwrap()
+    }
+
+    #[test]
+    fn only_values_past_u64_are_big() {
+        assert_eq!(number("18446744073709551615"), Number::Small(u64::MAX));
+        assert_eq!(number("00018446744073709551615"), Number::Small(u64::MAX));
+        assert!(number("18446744073709551616").is_big());
+        assert_eq!(number("18446744073709551616").to_string(), "18446744073709551616");
+
+        for s in ["", "-1", "+1", "1.0", "1 2", "x"] {
+            assert!(s.parse::<Number>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn counts_digits_around_powers_of_ten() {
+        assert_eq!(Number::from(0).count_digits(), 1);
+        assert_eq!(Number::from(9).count_digits(), 1);
+        assert_eq!(Number::from(10).count_digits(), 2);
+        assert_eq!(Number::from(10u64.pow(19) - 1).count_digits(), 19);
+        assert_eq!(Number::from(10u64.pow(19)).count_digits(), 20);
+        assert_eq!(Number::from(u64::MAX).count_digits(), 20);
+        assert_eq!(number("18446744073709551616").count_digits(), 20);
+        assert_eq!(number("99999999999999999999").count_digits(), 20);
+        assert_eq!(number("100000000000000000000").count_digits(), 21);
+
+        // Every length across where the f64 estimate could be off by one
+        for digits in 20..200 {
+            assert_eq!(number(&"9".repeat(digits)).count_digits(), digits as u32);
+            assert_eq!(number(&format!("1{}", "0".repeat(digits - 1))).count_digits(), digits as u32);
+        }
+    }
+
+    #[test]
+    fn splits_digits_either_side_of_u64() {
+        assert_eq!(Number::from(u64::MAX).split_digits(10), (Number::from(1844674407), Number::from(3709551615)));
+        assert_eq!(Number::from(1000).split_digits(2), (Number::from(10), Number::from(0)));
+        assert_eq!(Number::from(u64::MAX).split_digits(0), (Number::from(u64::MAX), Number::from(0)));
+        assert_eq!(Number::from(u64::MAX).split_digits(19), (Number::from(1), Number::from(8446744073709551615)));
+        assert_eq!(Number::from(u64::MAX).split_digits(20), (Number::from(0), Number::from(u64::MAX)));
+        assert_eq!(Number::from(u64::MAX).split_digits(25), (Number::from(0), Number::from(u64::MAX)));
+
+        // Halves of a big number that fit in a u64 have to come back Small
+        let (left, right) = number("1844674407370955161600000000000000000001").split_digits(20);
+        assert_eq!((left.clone(), right), (number("18446744073709551616"), Number::from(1)));
+        assert!(left.is_big());
+        let (left, right) = number("123456789012345678901234").split_digits(12);
+        assert_eq!((left, right), (Number::from(123456789012), Number::from(345678901234)));
+    }
+
+    #[test]
+    fn arithmetic_moves_between_small_and_big() {
+        let max = Number::from(u64::MAX);
+        let past_max = number("18446744073709551616");
+        assert_eq!(&max + &Number::from(1), past_max);
+        assert_eq!(&past_max + &Number::from(0), past_max);
+        assert_eq!(&max + &Number::from(0), max);
+
+        assert_eq!(Number::from(u64::MAX / 2024).mul(2024), Number::from(u64::MAX / 2024 * 2024));
+        assert_eq!(Number::from(u64::MAX / 2024 + 1).mul(2024), number("18446744073709552392"));
+        assert_eq!(past_max.mul(0), Number::from(0));
+        assert!(past_max.is_multiple_of(2) && past_max.is_multiple_of(1 << 63) && !past_max.is_multiple_of(3));
+        assert!(max.is_multiple_of(5) && !max.is_multiple_of(2));
+
+        let total: Number = [max.clone(), max.clone(), Number::from(2)].into_iter().sum();
+        assert_eq!(total, number("36893488147419103232"));
+    }
+}
@@ -0,0 +1,191 @@
+use std::fmt;
+use util::file::GenericParseError;
+use super::number::Number;
+
+pub type Stone = Number;
+
+// The puzzle's rules, in the same format as a rules file
+pub const PUZZLE_RULES: &str = "
+    eq 0 -> replace 1
+    even-digits -> split
+    any -> mul 2024
+";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Equals(Stone),
+    EvenDigits,
+    OddDigits,
+    DivisibleBy(u64),
+    Any,
+}
+
+impl Condition {
+    fn matches(&self, stone: &Stone) -> bool {
+        match self {
+            Condition::Equals(value) => stone == value,
+            Condition::EvenDigits => stone.count_digits().is_multiple_of(2),
+            Condition::OddDigits => !stone.count_digits().is_multiple_of(2),
+            Condition::DivisibleBy(divisor) => stone.is_multiple_of(*divisor),
+            Condition::Any => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transform {
+    Replace(Stone),
+
+    // The left half of the digits and the right half, as separate stones. With an odd number of
+    // digits the extra one goes to the left (12345 becomes 123 and 45).
+    Split,
+
+    Multiply(u64),
+}
+
+impl Transform {
+    fn apply(&self, stone: &Stone) -> Vec<Stone> {
+        match self {
+            Transform::Replace(value) => vec![value.clone()],
+            Transform::Split => {
+                let (left, right) = stone.split_digits(stone.count_digits() / 2);
+                vec![left, right]
+            },
+            Transform::Multiply(factor) => vec![stone.mul(*factor)],
+        }
+    }
+}
+
+// `<condition> -> <transform>`, e.g. `div 3 -> mul 7`, where the condition is one of `eq N`,
+// `even-digits`, `odd-digits`, `div N` or `any` and the transform is one of `replace N`, `split` or
+// `mul N`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub condition: Condition,
+    pub transform: Transform,
+}
+
+impl std::str::FromStr for Rule {
+    type Err = GenericParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || GenericParseError::ValueError(format!("Invalid rule: {}", s));
+        let (condition, transform) = s.split_once("->").ok_or_else(invalid)?;
+
+        let condition = match condition.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            ["eq", value] => Condition::Equals(value.parse::<Stone>()?),
+            ["even-digits"] => Condition::EvenDigits,
+            ["odd-digits"] => Condition::OddDigits,
+            ["div", divisor] => match divisor.parse::<u64>()? {
+                0 => return Err(invalid()),
+                divisor => Condition::DivisibleBy(divisor),
+            },
+            ["any"] => Condition::Any,
+            _ => return Err(invalid()),
+        };
+
+        let transform = match transform.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            ["replace", value] => Transform::Replace(value.parse::<Stone>()?),
+            ["split"] => Transform::Split,
+            ["mul", factor] => Transform::Multiply(factor.parse::<u64>()?),
+            _ => return Err(invalid()),
+        };
+
+        Ok(Rule{condition, transform})
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.condition {
+            Condition::Equals(value) => write!(f, "eq {}", value)?,
+            Condition::EvenDigits => write!(f, "even-digits")?,
+            Condition::OddDigits => write!(f, "odd-digits")?,
+            Condition::DivisibleBy(divisor) => write!(f, "div {}", divisor)?,
+            Condition::Any => write!(f, "any")?,
+        }
+        write!(f, " -> ")?;
+        match &self.transform {
+            Transform::Replace(value) => write!(f, "replace {}", value),
+            Transform::Split => write!(f, "split"),
+            Transform::Multiply(factor) => write!(f, "mul {}", factor),
+        }
+    }
+}
+
+// Rules are tried in order and the first one that matches is applied. A stone that matches none of
+// them stays as it is.
+pub struct RuleSet {
+    pub rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    // One rule per line, skipping blank lines and `#` comments
+    pub fn parse(text: &str) -> std::result::Result<RuleSet, GenericParseError> {
+        let rules = text.lines()
+            .map(|line| line.split('#').next().unwrap().trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| line.parse::<Rule>())
+            .collect::<std::result::Result<Vec<Rule>, GenericParseError>>()?;
+
+        Ok(RuleSet{rules})
+    }
+
+    pub fn blink(&self, stone: &Stone) -> Vec<Stone> {
+        match self.rules.iter().find(|rule| rule.condition.matches(stone)) {
+            Some(rule) => rule.transform.apply(stone),
+            None => vec![stone.clone()],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stones(values: &[u64]) -> Vec<Stone> {
+        values.iter().map(|&value| Stone::from(value)).collect()
+    }
+
+    #[test]
+    fn puzzle_rules_blink_like_the_example() {
+        let rules = RuleSet::parse(PUZZLE_RULES).unwrap();
+        assert_eq!(rules.rules.len(), 3);
+        assert_eq!(rules.blink(&Stone::from(0)), stones(&[1]));
+        assert_eq!(rules.blink(&Stone::from(1)), stones(&[2024]));
+        assert_eq!(rules.blink(&Stone::from(10)), stones(&[1, 0]));
+        assert_eq!(rules.blink(&Stone::from(99)), stones(&[9, 9]));
+        assert_eq!(rules.blink(&Stone::from(1000)), stones(&[10, 0]));
+        assert_eq!(rules.blink(&Stone::from(999)), stones(&[2021976]));
+    }
+
+    #[test]
+    fn rules_parse_and_display_the_same() {
+        for text in ["eq 0 -> replace 1", "even-digits -> split", "odd-digits -> mul 3", "div 7 -> replace 18446744073709551616", "any -> split"] {
+            assert_eq!(text.parse::<Rule>().unwrap().to_string(), text);
+        }
+        assert_eq!("  div   3->mul 7 ".parse::<Rule>().unwrap().to_string(), "div 3 -> mul 7");
+
+        for text in ["", "any", "any ->", "eq -> split", "eq x -> split", "div 0 -> split", "div -3 -> split", "odd -> split", "any -> mul", "any -> mul 1 2", "any -> replace -1"] {
+            assert!(text.parse::<Rule>().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_applies() {
+        let rules = RuleSet::parse("
+            # Split odd lengths with the extra digit going left
+            div 5 -> replace 5  # before the split rule, so it wins
+            odd-digits -> split
+        ").unwrap();
+        assert_eq!(rules.rules.len(), 2);
+        assert_eq!(rules.blink(&Stone::from(12345)), stones(&[5]));
+        assert_eq!(rules.blink(&Stone::from(12346)), stones(&[123, 46]));
+        assert_eq!(rules.blink(&Stone::from(7)), stones(&[7, 0]));
+
+        // Nothing matches an even number of digits, which stays as it is
+        assert_eq!(rules.blink(&Stone::from(12)), stones(&[12]));
+        assert!(RuleSet::parse("any -> split\nbad").is_err());
+        assert!(RuleSet::parse("\n # nothing\n").unwrap().rules.is_empty());
+    }
+}
@@ -1,3 +1,4 @@
+use std::io::{ErrorKind, Read};
 use util::res::Result;
 
 #[derive(Debug)]
@@ -31,108 +32,262 @@ struct Multiply {
     a: u32,
     b: u32,
     enabled: bool,
+    offset: u64, // Byte offset of the `m` that started this instruction
 }
 
-fn extract_multiplies(memory: &String) -> Vec<Multiply> {
-    let mut ret: Vec<Multiply> = vec![];
-    let mut state = ScanState::Garbage;
-    let mut pending_enabled: bool = true;
-    let mut enabled: bool = true;
-    let mut num1_str: String = "".to_owned();
-    let mut num2_str: String = "".to_owned();
-
-    // In this house we love a cute little state machine
-    for c in memory.chars() {
-        match (&state, c) {
-            (ScanState::AwaitingU, 'u') => state = ScanState::AwaitingL,
-            (ScanState::AwaitingL, 'l') => state = ScanState::AwaitingMulLeftParens,
-            (ScanState::AwaitingMulLeftParens, '(') => state = ScanState::AwaitingNum1Digit1,
+#[derive(Debug)]
+enum Instruction {
+    Multiply(Multiply),
+    Toggle { enabled: bool, offset: u64 }, // A `do()` or `don't()`
+}
+
+// Holds only the state machine and the (at most 3-digit) operands, so memory use
+// doesn't depend on input size at all
+struct Scanner {
+    state: ScanState,
+    pending_enabled: bool,
+    enabled: bool,
+    num1: u32,
+    num2: u32,
+    start: u64,
+    offset: u64,
+}
+
+impl Scanner {
+    fn new() -> Self {
+        Scanner {
+            state: ScanState::Garbage,
+            pending_enabled: true,
+            enabled: true,
+            num1: 0,
+            num2: 0,
+            start: 0,
+            offset: 0,
+        }
+    }
+
+    fn push(&mut self, c: char) -> Option<Instruction> {
+        let offset = self.offset;
+        self.offset += 1;
+
+        // Line breaks used to be joined away before scanning, so they can't break up an instruction
+        if c == '\n' || c == '\r' {
+            return None;
+        }
+
+        let mut found: Option<Instruction> = None;
+
+        // In this house we love a cute little state machine
+        self.state = match (&self.state, c) {
+            (ScanState::AwaitingU, 'u') => ScanState::AwaitingL,
+            (ScanState::AwaitingL, 'l') => ScanState::AwaitingMulLeftParens,
+            (ScanState::AwaitingMulLeftParens, '(') => ScanState::AwaitingNum1Digit1,
             (ScanState::AwaitingNum1Digit1, '0'..='9') => {
-                num1_str = c.to_string();
-                state = ScanState::AwaitingNum1Digit2OrComma;
+                self.num1 = digit(c);
+                ScanState::AwaitingNum1Digit2OrComma
             },
             (ScanState::AwaitingNum1Digit2OrComma, '0'..='9') => {
-                num1_str.push(c);
-                state = ScanState::AwaitingNum1Digit3OrComma;
+                self.num1 = self.num1 * 10 + digit(c);
+                ScanState::AwaitingNum1Digit3OrComma
             },
             (ScanState::AwaitingNum1Digit3OrComma, '0'..='9') => {
-                num1_str.push(c);
-                state = ScanState::AwaitingComma;
+                self.num1 = self.num1 * 10 + digit(c);
+                ScanState::AwaitingComma
             },
             (ScanState::AwaitingNum1Digit2OrComma | ScanState::AwaitingNum1Digit3OrComma | ScanState::AwaitingComma, ',') => {
-                state = ScanState::AwaitingNum2Digit1;
+                ScanState::AwaitingNum2Digit1
             },
             (ScanState::AwaitingNum2Digit1, '0'..='9') => {
-                num2_str = c.to_string();
-                state = ScanState::AwaitingNum2Digit2OrRightParens;
+                self.num2 = digit(c);
+                ScanState::AwaitingNum2Digit2OrRightParens
             },
             (ScanState::AwaitingNum2Digit2OrRightParens, '0'..='9') => {
-                num2_str.push(c);
-                state = ScanState::AwaitingNum2Digit3OrRightParens;
+                self.num2 = self.num2 * 10 + digit(c);
+                ScanState::AwaitingNum2Digit3OrRightParens
             },
             (ScanState::AwaitingNum2Digit3OrRightParens, '0'..='9') => {
-                num2_str.push(c);
-                state = ScanState::AwaitingMulRightParens;
+                self.num2 = self.num2 * 10 + digit(c);
+                ScanState::AwaitingMulRightParens
             },
             (ScanState::AwaitingNum2Digit2OrRightParens | ScanState::AwaitingNum2Digit3OrRightParens | ScanState::AwaitingMulRightParens, ')') => {
                 // We found a full multiply instruction!
-                ret.push(Multiply{
-                    a: num1_str.parse::<u32>().unwrap(),
-                    b: num2_str.parse::<u32>().unwrap(),
-                    enabled: enabled,
-                });
-                state = ScanState::Garbage;
+                found = Some(Instruction::Multiply(Multiply{
+                    a: self.num1,
+                    b: self.num2,
+                    enabled: self.enabled,
+                    offset: self.start,
+                }));
+                ScanState::Garbage
             },
-            (ScanState::AwaitingO, 'o') => state = ScanState::AwaitingNOrLeftParens,
+            (ScanState::AwaitingO, 'o') => ScanState::AwaitingNOrLeftParens,
             (ScanState::AwaitingNOrLeftParens, '(') => {
                 // Past the point of no return towards a `do()` instruction
-                pending_enabled = true;
-                state = ScanState::AwaitingDoDontRightParens;
+                self.pending_enabled = true;
+                ScanState::AwaitingDoDontRightParens
             },
             (ScanState::AwaitingNOrLeftParens, 'n') => {
                 // Past the point of no return towards a `don't()` instruction
-                pending_enabled = false;
-                state = ScanState::AwaitingApostrophe;
+                self.pending_enabled = false;
+                ScanState::AwaitingApostrophe
             },
-            (ScanState::AwaitingApostrophe, '\'') => state = ScanState::AwaitingT,
-            (ScanState::AwaitingT, 't') => state = ScanState::AwaitingDoDontLeftParens,
-            (ScanState::AwaitingDoDontLeftParens, '(') => state = ScanState::AwaitingDoDontRightParens,
+            (ScanState::AwaitingApostrophe, '\'') => ScanState::AwaitingT,
+            (ScanState::AwaitingT, 't') => ScanState::AwaitingDoDontLeftParens,
+            (ScanState::AwaitingDoDontLeftParens, '(') => ScanState::AwaitingDoDontRightParens,
             (ScanState::AwaitingDoDontRightParens, ')') => {
                 // We found a full `do()`/`don't()`` instruction!
-                enabled = pending_enabled;
-                state = ScanState::Garbage;
+                self.enabled = self.pending_enabled;
+                found = Some(Instruction::Toggle { enabled: self.enabled, offset: self.start });
+                ScanState::Garbage
             },
-            (_, 'm') => state = ScanState::AwaitingU, // Started `mul()`
-            (_, 'd') => state = ScanState::AwaitingO, // Started `do()`` or `don't()`
-            _ => state = ScanState::Garbage
-        }
+            (_, 'm') => {
+                self.start = offset;
+                ScanState::AwaitingU // Started `mul()`
+            },
+            (_, 'd') => {
+                self.start = offset;
+                ScanState::AwaitingO // Started `do()`` or `don't()`
+            },
+            _ => ScanState::Garbage
+        };
+
+        found
     }
+}
 
-    ret
+fn digit(c: char) -> u32 {
+    c.to_digit(10).unwrap()
 }
 
-fn part1(multiplies: &Vec<Multiply>) {
-    let total: u64 = multiplies.iter().map(|multiply| u64::from(multiply.a * multiply.b)).sum();
-    
-    println!("Found {:?} multiplies, total of products = {:?}", multiplies.len(), total);
+#[derive(Debug, Default)]
+struct Totals {
+    multiplies: u64,
+    total: u64,
+    enabled_multiplies: u64,
+    enabled_total: u64,
 }
 
-fn part2(multiplies: &Vec<Multiply>) {
-    let enabled_products = multiplies.iter().filter_map(|multiply| {
-        if multiply.enabled { Some(u64::from(multiply.a * multiply.b)) } else { None }
-    }).collect::<Vec<u64>>();
-    let total: u64 = enabled_products.iter().sum();
+impl Totals {
+    fn add(&mut self, multiply: &Multiply) {
+        let product = u64::from(multiply.a) * u64::from(multiply.b);
+        self.multiplies += 1;
+        self.total += product;
+        if multiply.enabled {
+            self.enabled_multiplies += 1;
+            self.enabled_total += product;
+        }
+    }
+}
+
+// Streams the input through the scanner in fixed-size chunks, reporting each instruction as it's matched
+fn scan<R: Read>(mut reader: R) -> Result<Totals> {
+    let mut scanner = Scanner::new();
+    let mut totals = Totals::default();
+    let mut buf = [0u8; 64 * 1024];
+
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        // Non-ASCII bytes can never be part of an instruction, so scanning bytes is as good as scanning chars
+        for &byte in &buf[..n] {
+            match scanner.push(char::from(byte)) {
+                Some(Instruction::Multiply(multiply)) => {
+                    totals.add(&multiply);
+                    println!(
+                        "@{}: mul({},{}){} -> part 1 total = {}, part 2 total = {}",
+                        multiply.offset, multiply.a, multiply.b, if multiply.enabled { "" } else { " (disabled)" },
+                        totals.total, totals.enabled_total,
+                    );
+                },
+                Some(Instruction::Toggle { enabled, offset }) => {
+                    println!("@{}: {}", offset, if enabled { "do()" } else { "don't()" });
+                },
+                None => {},
+            }
+        }
+    }
 
-    println!("Found {:?} enabled multiplies, total of products = {:?}", enabled_products.len(), total);
+    Ok(totals)
+}
+
+fn part1(totals: &Totals) {
+    println!("Found {:?} multiplies, total of products = {:?}", totals.multiplies, totals.total);
+}
+
+fn part2(totals: &Totals) {
+    println!("Found {:?} enabled multiplies, total of products = {:?}", totals.enabled_multiplies, totals.enabled_total);
 }
 
 fn main() -> Result<()> {
     let file_path = util::file::get_input_file_path();
-    let lines = util::file::read_lines_raw(file_path)?;
-    let multiplies =  extract_multiplies(&lines.join(""));
+    let reader = util::file::open_reader(file_path)?;
+    let totals = scan(reader)?;
 
-    part1(&multiplies);
-    part2(&multiplies);
+    part1(&totals);
+    part2(&totals);
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hands out one byte per read, so every instruction straddles a chunk boundary
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let Some((&first, rest)) = self.0.split_first() else { return Ok(0) };
+            buf[0] = first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    fn instructions(text: &str) -> Vec<Instruction> {
+        let mut scanner = Scanner::new();
+        text.chars().filter_map(|c| scanner.push(c)).collect()
+    }
+
+    #[test]
+    fn example_totals() {
+        let totals = scan("xmul(2,4)%&mul[3,7]!@^do_not_mul(5,5)+mul(32,64]then(mul(11,8)mul(8,5))".as_bytes()).unwrap();
+        assert_eq!((totals.multiplies, totals.total), (4, 161));
+
+        let totals = scan("xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))".as_bytes()).unwrap();
+        assert_eq!((totals.multiplies, totals.total), (4, 161));
+        assert_eq!((totals.enabled_multiplies, totals.enabled_total), (2, 48));
+    }
+
+    #[test]
+    fn reads_split_anywhere() {
+        let text = "mul(123,4)don't()mul(5,6)\ndo()mul(7,8)";
+        let totals = scan(Trickle(text.as_bytes())).unwrap();
+        assert_eq!((totals.total, totals.enabled_total), (492 + 30 + 56, 492 + 56));
+    }
+
+    #[test]
+    fn operands_are_one_to_three_digits() {
+        let totals = scan("mul(1,2)mul(999,999)mul(1000,2)mul(2,1000)mul(,2)mul(2,)mul( 2,2)".as_bytes()).unwrap();
+        assert_eq!((totals.multiplies, totals.total), (2, 2 + 999 * 999));
+    }
+
+    #[test]
+    fn line_breaks_are_skipped_and_offsets_kept() {
+        match instructions("ab\nmu\r\nl(3,\n4)").as_slice() {
+            [Instruction::Multiply(Multiply{a: 3, b: 4, enabled: true, offset: 3})] => {},
+            other => panic!("{:?}", other),
+        }
+
+        // An `m` or `d` part way through starts over from there
+        match instructions("mmul(2,2)ddon't()do(").as_slice() {
+            [Instruction::Multiply(Multiply{a: 2, b: 2, offset: 1, ..}), Instruction::Toggle{enabled: false, offset: 10}] => {},
+            other => panic!("{:?}", other),
+        }
+    }
+}
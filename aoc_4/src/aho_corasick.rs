@@ -0,0 +1,83 @@
+use std::collections::{HashMap, VecDeque};
+
+// Multi-pattern matcher over a dictionary of words. Feeding it a stream of characters one at a time
+// reports every dictionary word ending at each character, in time linear in the stream length
+// (plus the number of matches), no matter how many words are in the dictionary.
+pub struct AhoCorasick {
+    goto: Vec<HashMap<char, usize>>,
+    fail: Vec<usize>,
+    outputs: Vec<Vec<usize>>, // Indices of every word ending at each state, including via fail links
+    lengths: Vec<usize>,      // Length of each word, in characters
+}
+
+impl AhoCorasick {
+    pub const START: usize = 0;
+
+    pub fn new(words: &[&str]) -> AhoCorasick {
+        let mut goto: Vec<HashMap<char, usize>> = vec![HashMap::new()];
+        let mut outputs: Vec<Vec<usize>> = vec![vec![]];
+
+        // Build the trie
+        for (idx, word) in words.iter().enumerate().filter(|(_, word)| !word.is_empty()) {
+            let mut state = AhoCorasick::START;
+            for c in word.chars() {
+                state = match goto[state].get(&c) {
+                    Some(&next) => next,
+                    None => {
+                        goto.push(HashMap::new());
+                        outputs.push(vec![]);
+                        let next = goto.len() - 1;
+                        goto[state].insert(c, next);
+                        next
+                    },
+                };
+            }
+            outputs[state].push(idx);
+        }
+
+        // Breadth-first, so every state's fail link is finished before its children need it
+        let mut fail: Vec<usize> = vec![AhoCorasick::START; goto.len()];
+        let mut queue: VecDeque<usize> = goto[AhoCorasick::START].values().copied().collect();
+        while let Some(state) = queue.pop_front() {
+            let children: Vec<(char, usize)> = goto[state].iter().map(|(&c, &next)| (c, next)).collect();
+            for (c, next) in children {
+                let mut fallback = fail[state];
+                while fallback != AhoCorasick::START && !goto[fallback].contains_key(&c) {
+                    fallback = fail[fallback];
+                }
+                fail[next] = goto[fallback].get(&c).copied().filter(|&s| s != next).unwrap_or(AhoCorasick::START);
+
+                let inherited = outputs[fail[next]].clone();
+                outputs[next].extend(inherited);
+                queue.push_back(next);
+            }
+        }
+
+        AhoCorasick{
+            goto,
+            fail,
+            outputs,
+            lengths: words.iter().map(|word| word.chars().count()).collect(),
+        }
+    }
+
+    pub fn step(&self, mut state: usize, c: char) -> usize {
+        loop {
+            if let Some(&next) = self.goto[state].get(&c) {
+                return next;
+            }
+            if state == AhoCorasick::START {
+                return AhoCorasick::START;
+            }
+            state = self.fail[state];
+        }
+    }
+
+    pub fn matches(&self, state: usize) -> &[usize] {
+        &self.outputs[state]
+    }
+
+    pub fn word_len(&self, idx: usize) -> usize {
+        self.lengths[idx]
+    }
+}
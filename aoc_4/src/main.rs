@@ -1,4 +1,143 @@
+use std::path::PathBuf;
+use std::time::Instant;
+use clap::Parser;
 use util::res::Result;
+use aho_corasick::AhoCorasick;
+
+mod aho_corasick;
+
+#[derive(Parser, Debug)]
+struct Args {
+    #[arg(short, long)]
+    file: PathBuf,
+
+    // Comma-separated words to search for in part 1 instead of XMAS
+    #[arg(short, long, value_delimiter = ',', default_value = "XMAS")]
+    words: Vec<String>,
+
+    // File with one word to search for per line, instead of the word list
+    #[arg(short, long, conflicts_with = "words")]
+    dictionary: Option<PathBuf>,
+
+    // File holding the stencil to match in part 2 instead of the X-MAS cross (see Stencil for the
+    // format)
+    #[arg(short, long)]
+    stencil: Option<PathBuf>,
+
+    // Let words and stencils run off one edge of the grid and continue from the opposite edge
+    #[arg(long)]
+    wrap: bool,
+
+    // Time the automaton against checking every word at every cell, making sure they find the same
+    // matches
+    #[arg(short, long)]
+    bench: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Direction {
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+}
+
+impl Direction {
+    const ALL: [Direction; 8] = [
+        Direction::North,
+        Direction::NorthEast,
+        Direction::East,
+        Direction::SouthEast,
+        Direction::South,
+        Direction::SouthWest,
+        Direction::West,
+        Direction::NorthWest,
+    ];
+
+    // (row, column) step taken for each successive character
+    fn delta(&self) -> (isize, isize) {
+        match self {
+            Direction::North => (-1, 0),
+            Direction::NorthEast => (-1, 1),
+            Direction::East => (0, 1),
+            Direction::SouthEast => (1, 1),
+            Direction::South => (1, 0),
+            Direction::SouthWest => (1, -1),
+            Direction::West => (0, -1),
+            Direction::NorthWest => (-1, -1),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct WordMatch<'a> {
+    word: &'a str,
+    row: usize,
+    col: usize,
+    direction: Direction,
+}
+
+// A 2D pattern of characters, where `.` matches anything. For example, one orientation of the X-MAS cross:
+//
+// M.S
+// .A.
+// M.S
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Stencil {
+    cells: Vec<(usize, usize, char)>, // (row, column, character) of every non-wildcard cell
+    width: usize,
+    height: usize,
+}
+
+impl Stencil {
+    fn parse(text: &str) -> Stencil {
+        let rows: Vec<&str> = text.lines().map(|line| line.trim()).filter(|line| !line.is_empty()).collect();
+        let cells = rows.iter().enumerate().flat_map(|(row, line)| {
+            line.chars().enumerate().filter(|&(_, c)| c != '.').map(move |(col, c)| (row, col, c))
+        }).collect();
+
+        Stencil{
+            cells,
+            width: rows.iter().map(|line| line.chars().count()).max().unwrap_or(0),
+            height: rows.len(),
+        }
+    }
+
+    fn rotated_clockwise(&self) -> Stencil {
+        let mut cells: Vec<(usize, usize, char)> = self.cells.iter()
+            .map(|&(row, col, c)| (col, self.height - 1 - row, c))
+            .collect();
+        cells.sort();
+
+        Stencil{cells, width: self.height, height: self.width}
+    }
+
+    // Each distinct orientation of the stencil, paired with its clockwise rotation in degrees
+    fn rotations(&self) -> Vec<(u16, Stencil)> {
+        let mut ret: Vec<(u16, Stencil)> = vec![];
+        let mut stencil = self.clone();
+        stencil.cells.sort();
+        for degrees in [0, 90, 180, 270] {
+            if !ret.iter().any(|(_, seen)| *seen == stencil) {
+                ret.push((degrees, stencil.clone()));
+            }
+            stencil = stencil.rotated_clockwise();
+        }
+
+        ret
+    }
+}
+
+#[derive(Debug)]
+struct StencilMatch {
+    row: usize,
+    col: usize,
+    rotation: u16,
+}
 
 struct Crossword {
     lines: Vec<Vec<char>>,
@@ -7,127 +146,306 @@ struct Crossword {
 }
 
 impl Crossword {
-    // (column, row) pairs of spaces to check for XMAS/SAMX sequences
-    const XMAS_CHECKS: [[(usize, usize); 4]; 4] = [
-        // Horizontal
-        [(0, 0), (0, 1), (0, 2), (0, 3)],
-
-        // Vertical
-        [(0, 0), (1, 0), (2, 0), (3, 0)],
+    fn new(lines: &[String]) -> Crossword {
+        let char_lines: Vec<Vec<char>> = lines.iter().map(|line| line.chars().collect()).collect();
+        let width = char_lines.first().map_or(0, |line| line.len());
+        let height = char_lines.len();
 
-        // Diagonals (down-right, up-left)
-        [(0, 0), (1, 1), (2, 2), (3, 3)],
-        [(3, 0), (2, 1), (1, 2), (0, 3)],
-    ];
+        Crossword{lines: char_lines, width, height}
+    }
 
-    // (column, row) pairs of spaces to check for MAS crosses
-    // 5 elements: center A, M/S of one diagonal, M/S of other diagonal
-    const MAS_X_CHECKS: [[(usize, usize); 5]; 2] = [
-        // Diagonals are the same (top to bottom)
-        [(1, 1), (0, 0), (2, 2), (0, 2), (2, 0)],
+    // With wraparound, stepping off one edge of the grid continues from the opposite edge
+    fn get(&self, row: isize, col: isize, wrap: bool) -> Option<char> {
+        if self.width == 0 || self.height == 0 {
+            return None;
+        }
 
-        // Diagonals are in opposite order (top to bottom)
-        [(1, 1), (0, 0), (2, 2), (2, 0), (0, 2)],
-    ];
+        let (row, col) = if wrap {
+            (row.rem_euclid(self.height as isize), col.rem_euclid(self.width as isize))
+        } else {
+            (row, col)
+        };
 
-    fn new(lines: &Vec<String>) -> Crossword {
-        let width = lines[0].len();
-        let height = lines.len();
+        if row < 0 || col < 0 {
+            return None;
+        }
 
-        // Pad rows and columns with 3 extra dummy characters to make 4x4 kernel application easier
-        let mut char_lines: Vec<Vec<char>> = lines.iter().map(|line| {
-            let mut char_line = line.chars().collect::<Vec<char>>();
-            char_line.extend_from_slice(&['.', '.', '.']);
-            char_line
-        }).collect();
-        char_lines.extend(vec![vec!['.'; width + 3]; 3]);
+        self.lines.get(row as usize).and_then(|line| line.get(col as usize)).copied()
+    }
 
-        Crossword{lines: char_lines, width: width + 3, height: height + 3}
+    fn word_at(&self, word: &str, row: usize, col: usize, direction: Direction, wrap: bool) -> bool {
+        let (d_row, d_col) = direction.delta();
+        word.chars().enumerate().all(|(i, c)| {
+            let i = i as isize;
+            self.get(row as isize + d_row * i, col as isize + d_col * i, wrap) == Some(c)
+        })
     }
 
-    fn count_xmas_in_4_by_4(&self, top_left_row: usize, top_left_col: usize) -> usize {
-        // Out of bounds
-        if (top_left_row + 4) > self.height || (top_left_col + 4) > self.width {
-            return 0;
+    // Rays don't have ends once they wrap around, so only unwrapped searches can use the automaton
+    fn find_words<'a>(&self, words: &[&'a str], wrap: bool) -> Vec<WordMatch<'a>> {
+        if wrap {
+            self.find_words_directly(words, wrap)
+        } else {
+            self.find_words_with_automaton(words)
         }
+    }
 
-        Crossword::XMAS_CHECKS.iter().map(|check| {
-            let chars: [char; 4] = [
-                self.lines[top_left_row + check[0].0][top_left_col + check[0].1],
-                self.lines[top_left_row + check[1].0][top_left_col + check[1].1],
-                self.lines[top_left_row + check[2].0][top_left_col + check[2].1],
-                self.lines[top_left_row + check[3].0][top_left_col + check[3].1],
-            ];
-
-            match chars {
-                ['X', 'M', 'A', 'S'] | ['S', 'A', 'M', 'X'] => 1usize,
-                _ => 0usize,
+    // O(words × cells × directions), but handles wraparound
+    fn find_words_directly<'a>(&self, words: &[&'a str], wrap: bool) -> Vec<WordMatch<'a>> {
+        let mut ret: Vec<WordMatch<'a>> = vec![];
+        for row in 0..self.height {
+            for col in 0..self.width {
+                for &direction in Direction::ALL.iter() {
+                    for &word in words.iter().filter(|word| !word.is_empty()) {
+                        if self.word_at(word, row, col, direction, wrap) {
+                            ret.push(WordMatch{word, row, col, direction});
+                        }
+                    }
+                }
             }
-        }).sum()
+        }
+
+        ret
     }
 
-    fn count_xmas_in_grid(&self) -> usize {
-        let mut count: usize = 0;
-        for row in 0..=(self.height - 4) {
-            for col in 0..=(self.width - 4) {
-                count += self.count_xmas_in_4_by_4(row, col);
+    // Every maximal straight line of cells in the given direction, each starting at the grid edge
+    fn rays(&self, direction: Direction) -> Vec<Vec<(usize, usize)>> {
+        let (d_row, d_col) = direction.delta();
+        let in_bounds = |row: isize, col: isize| self.get(row, col, false).is_some();
+
+        let mut ret: Vec<Vec<(usize, usize)>> = vec![];
+        for row in 0..self.height as isize {
+            for col in 0..self.width as isize {
+                if in_bounds(row - d_row, col - d_col) {
+                    continue; // Not the start of a ray
+                }
+
+                let mut ray: Vec<(usize, usize)> = vec![];
+                let (mut r, mut c) = (row, col);
+                while in_bounds(r, c) {
+                    ray.push((r as usize, c as usize));
+                    r += d_row;
+                    c += d_col;
+                }
+                ret.push(ray);
             }
         }
 
-        count
+        ret
     }
 
-    fn count_max_s_in_3_by_3(&self, top_left_row: usize, top_left_col: usize) -> usize {
-        // Out of bounds
-        if (top_left_row + 3) > self.height || (top_left_col + 3) > self.width {
-            return 0;
+    // Streams every row, column and diagonal (in both directions) through one automaton built over
+    // the whole dictionary, so each cell is visited once per direction regardless of dictionary size
+    fn find_words_with_automaton<'a>(&self, words: &[&'a str]) -> Vec<WordMatch<'a>> {
+        let automaton = AhoCorasick::new(words);
+        let mut ret: Vec<WordMatch<'a>> = vec![];
+        for &direction in Direction::ALL.iter() {
+            for ray in self.rays(direction) {
+                let mut state = AhoCorasick::START;
+                for (i, &(row, col)) in ray.iter().enumerate() {
+                    state = automaton.step(state, self.lines[row][col]);
+                    for &idx in automaton.matches(state) {
+                        let (row, col) = ray[i + 1 - automaton.word_len(idx)];
+                        ret.push(WordMatch{word: words[idx], row, col, direction});
+                    }
+                }
+            }
         }
 
-        Crossword::MAS_X_CHECKS.iter().map(|check| {
-            let chars: [char; 5] = [
-                self.lines[top_left_row + check[0].0][top_left_col + check[0].1],
-                self.lines[top_left_row + check[1].0][top_left_col + check[1].1],
-                self.lines[top_left_row + check[2].0][top_left_col + check[2].1],
-                self.lines[top_left_row + check[3].0][top_left_col + check[3].1],
-                self.lines[top_left_row + check[4].0][top_left_col + check[4].1],
-            ];
-
-            match chars {
-                ['A', 'M', 'S', 'M', 'S'] | ['A', 'S', 'M', 'S', 'M'] => 1usize,
-                _ => 0usize,
-            }
-        }).sum()
+        ret
+    }
+
+    fn stencil_at(&self, stencil: &Stencil, row: usize, col: usize, wrap: bool) -> bool {
+        stencil.cells.iter().all(|&(d_row, d_col, c)| {
+            self.get((row + d_row) as isize, (col + d_col) as isize, wrap) == Some(c)
+        })
     }
 
-    fn count_mas_x_in_grid(&self) -> usize {
-        let mut count: usize = 0;
-        for row in 0..=(self.height - 3) {
-            for col in 0..=(self.width - 3) {
-                count += self.count_max_s_in_3_by_3(row, col);
+    // Matches are reported by the top-left corner of the (rotated) stencil's bounding box
+    fn find_stencil(&self, stencil: &Stencil, wrap: bool) -> Vec<StencilMatch> {
+        let mut ret: Vec<StencilMatch> = vec![];
+        for (rotation, rotated) in stencil.rotations() {
+            let (max_row, max_col) = if wrap {
+                (self.height, self.width)
+            } else {
+                ((self.height + 1).saturating_sub(rotated.height), (self.width + 1).saturating_sub(rotated.width))
+            };
+
+            for row in 0..max_row {
+                for col in 0..max_col {
+                    if self.stencil_at(&rotated, row, col, wrap) {
+                        ret.push(StencilMatch{row, col, rotation});
+                    }
+                }
             }
         }
 
-        count
+        ret
+    }
+}
+
+const MAS_X_STENCIL: &str = "
+    M.S
+    .A.
+    M.S
+";
+
+fn bench(crossword: &Crossword, words: &[&str]) -> Result<()> {
+    let start = Instant::now();
+    let mut direct = crossword.find_words_directly(words, false);
+    println!("Checking every word at every cell: {} matches in {:?}", direct.len(), start.elapsed());
+
+    let start = Instant::now();
+    let mut automaton = crossword.find_words_with_automaton(words);
+    println!("Aho-Corasick automaton: {} matches in {:?}", automaton.len(), start.elapsed());
+
+    direct.sort();
+    automaton.sort();
+    if direct != automaton {
+        return Err(From::from(format!("Searches disagree on {} words", words.len())));
     }
+
+    println!("Searches agree on all {} words", words.len());
+    Ok(())
 }
 
-fn part1(crossword: &Crossword) {
-    let xmas_count = crossword.count_xmas_in_grid();
-    println!("# of occurrences of XMAS in crossword: {:?}", xmas_count);
+fn part1(crossword: &Crossword, words: &[&str], wrap: bool) {
+    let matches = crossword.find_words(words, wrap);
+    for m in matches.iter() {
+        println!("{} at (row {}, col {}) going {:?}", m.word, m.row, m.col, m.direction);
+    }
+
+    let searched = match words {
+        [word] => word.to_string(),
+        _ => format!("{} words", words.len()),
+    };
+    println!("# of occurrences of {} in crossword: {:?}", searched, matches.len());
 }
 
-fn part2(crossword: &Crossword) {
-    let mas_x_count = crossword.count_mas_x_in_grid();
-    println!("# of occurrences of cross-MAS in crossword: {:?}", mas_x_count);
+fn part2(crossword: &Crossword, stencil: &Stencil, wrap: bool) {
+    let matches = crossword.find_stencil(stencil, wrap);
+    for m in matches.iter() {
+        println!("Stencil at (row {}, col {}) rotated {}°", m.row, m.col, m.rotation);
+    }
+
+    println!("# of occurrences of the stencil in crossword: {:?}", matches.len());
 }
 
 fn main() -> Result<()> {
-    let file_path = util::file::get_input_file_path();
-    let lines = util::file::read_lines_raw(file_path)?;
+    let args = Args::parse();
+    let lines = util::file::read_lines_raw(args.file)?;
     let crossword = Crossword::new(&lines);
 
-    part1(&crossword);
-    part2(&crossword);
+    let words: Vec<String> = match args.dictionary {
+        Some(dictionary_path) => util::file::read_lines_raw(dictionary_path)?.into_iter()
+            .map(|line| line.trim().to_owned())
+            .filter(|line| !line.is_empty())
+            .collect(),
+        None => args.words,
+    };
+    let words: Vec<&str> = words.iter().map(|word| word.as_str()).collect();
+
+    if args.bench {
+        return bench(&crossword, &words);
+    }
+
+    let stencil = match args.stencil {
+        Some(stencil_path) => Stencil::parse(&util::file::read_to_string(stencil_path)?),
+        None => Stencil::parse(MAS_X_STENCIL),
+    };
+
+    part1(&crossword, &words, args.wrap);
+    part2(&crossword, &stencil, args.wrap);
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "MMMSXXMASM
+MSAMXMSMSA
+AMXSXMAAMM
+MSAMASMSMX
+XMASAMXAMM
+XXAMMXXAMA
+SMSMSASXSS
+SAXAMASAAA
+MAMMMXMMMM
+MXMXAXMASX";
+
+    fn parse_crossword(text: &str) -> Crossword {
+        Crossword::new(&text.lines().map(|line| line.to_owned()).collect::<Vec<String>>())
+    }
+
+    #[test]
+    fn example_counts() {
+        let crossword = parse_crossword(EXAMPLE);
+        assert_eq!(crossword.find_words(&["XMAS"], false).len(), 18);
+        assert_eq!(crossword.find_stencil(&Stencil::parse(MAS_X_STENCIL), false).len(), 9);
+    }
+
+    #[test]
+    fn automaton_agrees_with_direct_search() {
+        // Overlapping words, prefixes and suffixes of each other, palindromes, single letters,
+        // duplicates, words that never appear and words longer than the grid
+        let words = ["XMAS", "MAS", "AS", "SAMX", "MM", "M", "XMASAMX", "AMA", "MAS", "QQQ", "MMMSXXMASMX", ""];
+        let crossword = parse_crossword(EXAMPLE);
+
+        let mut direct = crossword.find_words_directly(&words, false);
+        let mut automaton = crossword.find_words_with_automaton(&words);
+        assert!(direct.len() > 100);
+        direct.sort();
+        automaton.sort();
+        assert_eq!(direct, automaton);
+    }
+
+    #[test]
+    fn automaton_handles_every_substring_as_a_dictionary() {
+        let crossword = parse_crossword(EXAMPLE);
+        let mut words: Vec<&str> = EXAMPLE.lines().flat_map(|line| {
+            (0..line.len()).flat_map(move |start| (start + 1..=line.len()).map(move |end| &line[start..end]))
+        }).collect();
+        words.sort();
+        words.dedup();
+
+        let mut direct = crossword.find_words_directly(&words, false);
+        let mut automaton = crossword.find_words_with_automaton(&words);
+        direct.sort();
+        automaton.sort();
+        assert_eq!(direct, automaton);
+        assert!(bench(&crossword, &words).is_ok());
+    }
+
+    #[test]
+    fn words_only_wrap_when_asked() {
+        let crossword = parse_crossword("ASXM\n....\n....");
+        assert_eq!(crossword.find_words(&["XMAS"], false).len(), 0);
+
+        let matches = crossword.find_words(&["XMAS"], true);
+        assert_eq!(matches.len(), 1);
+        assert_eq!((matches[0].row, matches[0].col, matches[0].direction), (0, 2, Direction::East));
+
+        // Down from the bottom row back through the top
+        let crossword = parse_crossword("M...\nA...\nS...\nX...");
+        let matches = crossword.find_words(&["XMAS"], true);
+        assert_eq!(matches.len(), 1);
+        assert_eq!((matches[0].row, matches[0].col, matches[0].direction), (3, 0, Direction::South));
+    }
+
+    #[test]
+    fn stencils_only_wrap_when_asked() {
+        let crossword = parse_crossword(".A.\nM.S\n...\nM.S");
+        let stencil = Stencil::parse(MAS_X_STENCIL);
+        assert_eq!(crossword.find_stencil(&stencil, false).len(), 0);
+        assert_eq!(crossword.find_stencil(&stencil, true).len(), 1);
+    }
+
+    #[test]
+    fn stencil_rotations_skip_symmetric_repeats() {
+        assert_eq!(Stencil::parse(MAS_X_STENCIL).rotations().len(), 4);
+        assert_eq!(Stencil::parse("M.M\n.A.\nM.M").rotations().len(), 1);
+        assert_eq!(Stencil::parse("MAS").rotations().len(), 4);
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use super::ordering::{OrderingRule, Page, Rules};
+
+#[derive(Debug)]
+pub enum Status {
+    Correct,
+    Rearranged(Page), // Middle page number once put in canonical order
+    Ambiguous(Page, String), // Middle page number of the best-effort order, and why it isn't the only one
+    Unfixable(String),
+}
+
+struct Entry {
+    pages: Vec<Page>,
+    status: Status,
+}
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub correct: usize,
+    pub rearranged: usize,
+    pub unfixable: usize,
+    pub correct_middle_sum: Page,
+    pub rearranged_middle_sum: Page,
+}
+
+// Keeps every update classified as rules come and go. A rule only affects updates containing both
+// of its pages, so only those get re-classified when it changes.
+pub struct Engine {
+    rules: Rules,
+    updates: Vec<Entry>,
+    by_page: HashMap<Page, Vec<usize>>, // Indices of every update containing a given page
+}
+
+impl Engine {
+    pub fn new(rules: Rules) -> Engine {
+        Engine{rules, updates: vec![], by_page: HashMap::new()}
+    }
+
+    fn classify(&self, pages: &[Page]) -> Status {
+        if self.rules.is_ordered(pages) {
+            return Status::Correct;
+        }
+
+        match self.rules.canonical_order(pages) {
+            Ok(order) => Status::Rearranged(order[order.len() / 2]),
+            Err(e) => match e.best_effort_order() {
+                Some(order) => Status::Ambiguous(order[order.len() / 2], e.to_string()),
+                None => Status::Unfixable(e.to_string()),
+            },
+        }
+    }
+
+    pub fn add_update(&mut self, pages: Vec<Page>) -> &Status {
+        let idx = self.updates.len();
+        for &page in pages.iter() {
+            let containing = self.by_page.entry(page).or_default();
+            if containing.last() != Some(&idx) {
+                containing.push(idx);
+            }
+        }
+
+        let status = self.classify(&pages);
+        self.updates.push(Entry{pages, status});
+        &self.updates[idx].status
+    }
+
+    // Both return how many updates had to be re-classified
+    pub fn add_rule(&mut self, rule: OrderingRule) -> usize {
+        if !self.rules.add(rule) {
+            return 0;
+        }
+
+        self.reclassify(rule)
+    }
+
+    pub fn remove_rule(&mut self, rule: OrderingRule) -> usize {
+        if !self.rules.remove(rule) {
+            return 0;
+        }
+
+        self.reclassify(rule)
+    }
+
+    fn reclassify(&mut self, rule: OrderingRule) -> usize {
+        let (Some(with_before), Some(with_after)) = (self.by_page.get(&rule.before), self.by_page.get(&rule.after)) else {
+            return 0;
+        };
+
+        // Both lists are sorted by update index, so scan the shorter one and look up the longer one
+        let (shorter, longer) = if with_before.len() <= with_after.len() { (with_before, with_after) } else { (with_after, with_before) };
+        let affected: Vec<usize> = shorter.iter().copied().filter(|idx| longer.binary_search(idx).is_ok()).collect();
+
+        for &idx in affected.iter() {
+            self.updates[idx].status = self.classify(&self.updates[idx].pages);
+        }
+
+        affected.len()
+    }
+
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary::default();
+        for entry in self.updates.iter() {
+            match &entry.status {
+                Status::Correct => {
+                    summary.correct += 1;
+                    summary.correct_middle_sum += entry.pages[entry.pages.len() / 2];
+                },
+                Status::Rearranged(middle) | Status::Ambiguous(middle, _) => {
+                    summary.rearranged += 1;
+                    summary.rearranged_middle_sum += middle;
+                },
+                Status::Unfixable(_) => summary.unfixable += 1,
+            }
+        }
+
+        summary
+    }
+}
@@ -1,34 +1,66 @@
+use std::io::{self, prelude::*};
+use std::path::PathBuf;
+use clap::{Parser, ValueEnum};
 use util::res::Result;
 use util::file::GenericParseError;
+use ordering::{OrderingRule, Page, Rules};
+use report::UpdateReport;
+use engine::{Engine, Status};
 
-struct OrderingRule {
-    before: u8,
-    after: u8,
+mod engine;
+mod ordering;
+mod report;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ReportFormat {
+    Human,
+    Json,
 }
 
-struct Update {
-    pages: Vec<u8>
+#[derive(Parser, Debug)]
+struct Args {
+    #[arg(short, long, required_unless_present = "serve")]
+    file: Option<PathBuf>,
+
+    // Diagnose every incorrectly-ordered update
+    #[arg(short, long, value_enum, conflicts_with = "serve")]
+    report: Option<ReportFormat>,
+
+    // Read rule and update changes from stdin (after loading the input file, if any)
+    #[arg(short, long)]
+    serve: bool,
 }
 
-impl std::str::FromStr for OrderingRule {
+// Line-oriented protocol for `--serve`
+enum Command {
+    AddRule(OrderingRule),    // RULE a|b
+    RemoveRule(OrderingRule), // UNRULE a|b
+    AddUpdate(Update),        // UPDATE 1,2,3
+    Query,                    // QUERY
+}
+
+impl std::str::FromStr for Command {
     type Err = GenericParseError;
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        let parts: Vec<&str> = s.split('|').collect();
-        if parts.len() != 2 {
-            return Err(GenericParseError::ValueError(format!("Invalid ordering rule: {}", s).to_owned()))
+        let (verb, arg) = s.trim().split_once(' ').unwrap_or((s.trim(), ""));
+        match verb {
+            "RULE" => Ok(Command::AddRule(arg.trim().parse::<OrderingRule>()?)),
+            "UNRULE" => Ok(Command::RemoveRule(arg.trim().parse::<OrderingRule>()?)),
+            "UPDATE" => Ok(Command::AddUpdate(arg.trim().parse::<Update>()?)),
+            "QUERY" => Ok(Command::Query),
+            _ => Err(GenericParseError::ValueError(format!("Invalid command: {}", s))),
         }
-
-        Ok(OrderingRule{
-            before: parts[0].parse::<u8>()?,
-            after: parts[1].parse::<u8>()?,
-        })
     }
 }
 
+struct Update {
+    pages: Vec<Page>
+}
+
 impl Update {
-    fn middle_page_number<T: From<u8>>(&self) -> T {
-        T::from(self.pages[self.pages.len() / 2])
+    fn middle_page_number(&self) -> Page {
+        self.pages[self.pages.len() / 2]
     }
 }
 
@@ -36,13 +68,13 @@ impl std::str::FromStr for Update {
     type Err = GenericParseError;
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        let pages = s.split(',').map(|raw_num| raw_num.parse::<u8>().map_err(|e| e.into()))
-            .collect::<std::result::Result<Vec<u8>, Self::Err>>()?;
+        let pages = s.split(',').map(|raw_num| raw_num.parse::<Page>().map_err(|e| e.into()))
+            .collect::<std::result::Result<Vec<Page>, Self::Err>>()?;
         Ok(Update{pages})
     }
 }
 
-fn parse_ordering_rules_and_updates(lines: &Vec<String>) -> Result<(Vec<OrderingRule>, Vec<Update>)> {
+fn parse_ordering_rules_and_updates(lines: &[String]) -> Result<(Vec<OrderingRule>, Vec<Update>)> {
     let mut rules: Vec<OrderingRule> = vec![];
     let mut updates: Vec<Update> = vec![];
 
@@ -64,92 +96,234 @@ fn parse_ordering_rules_and_updates(lines: &Vec<String>) -> Result<(Vec<Ordering
     Ok((rules, updates))
 }
 
-fn generate_preceding_bitmaps(ordering_rules: &Vec<OrderingRule>) -> [u128; 100] {
-    let mut ret: [u128; 100] = [0u128; 100];
-    for ordering_rule in ordering_rules {
-        let mask: u128 = 1u128 << u128::from(ordering_rule.before);
-        ret[usize::from(ordering_rule.after)] |= mask;
-    }
-    ret
+fn categorize_updates(updates: Vec<Update>, rules: &Rules) -> (Vec<Update>, Vec<Update>) {
+    updates.into_iter().partition(|update| rules.is_ordered(&update.pages))
 }
 
-fn categorize_updates(updates: Vec<Update>, preceding_bitmaps: &[u128; 100]) -> (Vec<Update>, Vec<Update>) {
-    updates.into_iter().partition(|update| {
-        let mut cannot_appear_bitmap = 0u128;
-        for page in &update.pages {
-            let mask = 1u128 << u128::from(*page);
-            if (cannot_appear_bitmap & mask) != 0 {
-                // println!("cannot appear");
-                return false
-            }
+fn part1(correctly_ordered: &[Update]) {
+    let middle_page_sum: Page = correctly_ordered.iter().map(|update| update.middle_page_number()).sum();
+
+    println!("Middle page number sum of correctly-ordered updates: {:?}", middle_page_sum);
+}
 
-            cannot_appear_bitmap |= preceding_bitmaps[usize::from(*page)];
+// Updates that could go more than one way are rearranged into the best-effort order anyway, as
+// they always were, and only those with a cycle or a repeated page are left out
+fn rearrange(incorrectly_ordered: &[Update], rules: &Rules) -> Vec<Update> {
+    incorrectly_ordered.iter().filter_map(|update| {
+        match rules.canonical_order(&update.pages) {
+            Ok(pages) => Some(Update{pages}),
+            Err(e) => match e.best_effort_order() {
+                Some(order) => {
+                    println!("Rearranging update {:?} to {:?}, but it isn't the only order: {}", update.pages, order, e);
+                    Some(Update{pages: order.to_vec()})
+                },
+                None => {
+                    println!("Skipping update {:?}: {}", update.pages, e);
+                    None
+                },
+            },
         }
-        true
-    })
+    }).collect()
 }
 
-fn part1(correctly_ordered: &Vec<Update>) {
-    let middle_page_sum: u32 = correctly_ordered.iter().map(|update| update.middle_page_number::<u32>()).sum();
+// With `diagnose`, first goes through what's wrong with each update and how to fix it
+fn part2(incorrectly_ordered: &[Update], rules: &Rules, diagnose: bool) {
+    if diagnose {
+        for update in incorrectly_ordered {
+            println!("{}", UpdateReport::new(&update.pages, rules).to_human());
+        }
+    }
 
-    println!("Middle page number sum of correctly-ordered updates: {:?}", middle_page_sum);
+    let middle_page_sum: Page = rearrange(incorrectly_ordered, rules).iter().map(|update| update.middle_page_number()).sum();
+
+    println!("Middle page number sum of rearranged incorrectly-ordered updates: {:?}", middle_page_sum);
 }
 
-fn part2(incorrectly_ordered: &Vec<Update>, preceding_bitmaps: &[u128; 100]) {
-    let rearranged: Vec<Update> = incorrectly_ordered.iter().map(|update| {
-        let mut pages = update.pages.clone();
+fn json_report(incorrectly_ordered: &[Update], rules: &Rules) -> String {
+    let reports = incorrectly_ordered.iter()
+        .map(|update| UpdateReport::new(&update.pages, rules).to_json())
+        .collect::<Vec<String>>();
+    format!("[{}]", reports.join(","))
+}
 
-        // Construct bitmaps of page numbers appearing after a given index in pages
-        let mut appearing_after_masks = vec![0u128; pages.len()];
-        for i in (0..(pages.len() - 1)).rev() {
-            let mask = 1u128 << u128::from(pages[i + 1]);
-            appearing_after_masks[i] = appearing_after_masks[i + 1] | mask;
-        }
+fn serve(rules: Rules, updates: Vec<Update>) -> Result<()> {
+    let mut engine = Engine::new(rules);
+    for update in updates {
+        engine.add_update(update.pages);
+    }
 
-        // It's bubblesort time baby
-        let mut start_idx = 0;
-        while start_idx < (pages.len() - 1) {
-            for i in start_idx..(pages.len() - 1) {
-                // There are no page numbers after this one that must be before it, stop swapping
-                if (appearing_after_masks[i] & preceding_bitmaps[usize::from(pages[i])]) == 0 {
-                    // If this happened with no swaps, shift forward to just sort the remainder of the list
-                    if i == start_idx {
-                        start_idx += 1;
-                    }
-
-                    break
-                }
-
-                // Update appearing_after_masks to reflect that what _was_ after the entry
-                // at the current index now includes the page number at the current index
-                // and excludes the page number at the following index (consequence of
-                // swapping the two pages)
-                let mask = 1u128 << u128::from(pages[i]);
-                appearing_after_masks[i] = appearing_after_masks[i + 1] | mask;
-
-                // Actually swap the pages
-                pages.swap(i, i + 1);
-            }
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
         }
 
-        Update{pages}
-    }).collect();
-    let middle_page_sum: u32 = rearranged.iter().map(|update| update.middle_page_number::<u32>()).sum();
+        let response = match line.parse::<Command>() {
+            Ok(Command::AddRule(rule)) => format!("OK reclassified {}", engine.add_rule(rule)),
+            Ok(Command::RemoveRule(rule)) => format!("OK reclassified {}", engine.remove_rule(rule)),
+            Ok(Command::AddUpdate(update)) => match engine.add_update(update.pages) {
+                Status::Correct => "OK correct".to_owned(),
+                Status::Rearranged(_) => "OK incorrect".to_owned(),
+                Status::Ambiguous(_, e) => format!("OK incorrect, ambiguous: {}", e),
+                Status::Unfixable(e) => format!("OK unfixable: {}", e),
+            },
+            Ok(Command::Query) => {
+                let summary = engine.summary();
+                format!(
+                    "correct={} rearranged={} unfixable={} part1={} part2={}",
+                    summary.correct, summary.rearranged, summary.unfixable,
+                    summary.correct_middle_sum, summary.rearranged_middle_sum,
+                )
+            },
+            Err(e) => format!("ERR {}", e),
+        };
+        println!("{}", response);
+    }
 
-    println!("Middle page number sum of rearranged incorrectly-ordered updates: {:?}", middle_page_sum);
+    Ok(())
 }
 
 fn main() -> Result<()> {
-    let file_path = util::file::get_input_file_path();
-    let lines = util::file::read_lines_raw(file_path)?;
+    let args = Args::parse();
+    let lines = match args.file {
+        Some(file_path) => util::file::read_lines_raw(file_path)?,
+        None => vec![],
+    };
 
     // Preprocessing :)
     let (ordering_rules, updates) = parse_ordering_rules_and_updates(&lines)?;
-    let preceding_bitmaps = generate_preceding_bitmaps(&ordering_rules);
-    let (correctly_ordered, incorrectly_ordered) = categorize_updates(updates, &preceding_bitmaps);
+    let rules = Rules::new(&ordering_rules);
+    if args.serve {
+        return serve(rules, updates);
+    }
+
+    let (correctly_ordered, incorrectly_ordered) = categorize_updates(updates, &rules);
+    if let Some(ReportFormat::Json) = args.report {
+        println!("{}", json_report(&incorrectly_ordered, &rules));
+        return Ok(());
+    }
 
     part1(&correctly_ordered);
-    part2(&incorrectly_ordered, &preceding_bitmaps);
+    part2(&incorrectly_ordered, &rules, args.report.is_some());
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ordering::OrderingError;
+
+    const EXAMPLE: &str = "47|53
+97|13
+97|61
+97|47
+75|29
+61|13
+75|53
+29|13
+97|29
+53|29
+61|53
+97|53
+61|29
+47|13
+75|47
+97|75
+47|61
+75|61
+47|29
+75|13
+53|13
+
+75,47,61,53,29
+97,61,53,29,13
+75,29,13
+75,97,47,61,53
+61,13,29
+97,13,75,29,47";
+
+    fn example() -> (Rules, Vec<Update>) {
+        let lines: Vec<String> = EXAMPLE.lines().map(|line| line.to_owned()).collect();
+        let (ordering_rules, updates) = parse_ordering_rules_and_updates(&lines).unwrap();
+        (Rules::new(&ordering_rules), updates)
+    }
+
+    #[test]
+    fn example_middle_page_sums() {
+        let (rules, updates) = example();
+        let (correctly_ordered, incorrectly_ordered) = categorize_updates(updates, &rules);
+        assert_eq!(correctly_ordered.iter().map(|update| update.middle_page_number()).sum::<Page>(), 143);
+
+        let rearranged: Page = rearrange(&incorrectly_ordered, &rules).iter().map(|update| update.middle_page_number()).sum();
+        assert_eq!(rearranged, 123);
+    }
+
+    #[test]
+    fn ambiguous_updates_still_count_towards_part_2() {
+        // Nothing orders 5 against 2 or 3, so 1,2,5 and 1,5,2 both follow the rules
+        let rules = Rules::new(&["1|2", "2|3", "1|5"].map(|rule| rule.parse::<OrderingRule>().unwrap()));
+        let updates: Vec<Update> = ["5,2,1", "3,2,1", "4,4,1,2", "2,1"].iter().map(|update| update.parse::<Update>().unwrap()).collect();
+        let rearranged = rearrange(&updates, &rules);
+        assert_eq!(rearranged.iter().map(|update| update.pages.clone()).collect::<Vec<Vec<Page>>>(), vec![vec![1, 2, 5], vec![1, 2, 3], vec![1, 2]]);
+
+        let mut engine = Engine::new(rules);
+        assert!(matches!(engine.add_update(vec![5, 2, 1]), Status::Ambiguous(2, _)));
+        engine.add_rule(OrderingRule{before: 3, after: 1});
+        assert!(matches!(engine.add_update(vec![3, 2, 1]), Status::Unfixable(_)));
+        let summary = engine.summary();
+        assert_eq!((summary.rearranged, summary.unfixable, summary.rearranged_middle_sum), (1, 1, 2));
+    }
+
+    #[test]
+    fn reports_count_swaps_and_moves() {
+        let (rules, _) = example();
+        let report = UpdateReport::new(&[97, 13, 75, 29, 47], &rules);
+        let fix = report.fix.as_ref().unwrap();
+        assert_eq!(fix.order, vec![97, 75, 47, 29, 13]);
+        assert_eq!((fix.swaps, fix.moves), (2, 2));
+        assert_eq!(report.violations.len(), 4);
+    }
+
+    #[test]
+    fn repeated_pages_are_reported_instead_of_fixed() {
+        let (rules, _) = example();
+        let report = UpdateReport::new(&[29, 47, 53, 47, 13, 29], &rules);
+        assert!(matches!(&report.fix, Err(OrderingError::Repeated(pages)) if *pages == vec![29, 47]));
+        assert!(report.to_human().contains("can't be fixed: Update repeats pages 29, 47"));
+        assert!(report.to_json().contains("\"fixed_order\":null"));
+
+        let updates = ["29,47,53,47,13,29", "61,13,29"].map(|update| update.parse::<Update>().unwrap());
+        let json = json_report(&updates, &rules);
+        assert!(json.starts_with("[{\"pages\":[29,47,53,47,13,29]") && json.contains("},{\"pages\":[61,13,29]") && json.ends_with("}]"));
+
+        let mut engine = Engine::new(rules);
+        assert!(matches!(engine.add_update(vec![53, 47, 53]), Status::Unfixable(_)));
+    }
+
+    #[test]
+    fn engine_reclassifies_updates_as_rules_change() {
+        let (rules, updates) = example();
+        let mut engine = Engine::new(rules);
+        for update in updates {
+            engine.add_update(update.pages);
+        }
+
+        let summary = engine.summary();
+        assert_eq!((summary.correct, summary.rearranged, summary.unfixable), (3, 3, 0));
+        assert_eq!((summary.correct_middle_sum, summary.rearranged_middle_sum), (143, 123));
+
+        // Only 75,97,47,61,53 and 97,13,75,29,47 have both pages, and flipping the rule fixes the first
+        assert_eq!(engine.remove_rule(OrderingRule{before: 97, after: 75}), 2);
+        assert_eq!(engine.remove_rule(OrderingRule{before: 97, after: 75}), 0);
+        assert_eq!(engine.add_rule(OrderingRule{before: 75, after: 97}), 2);
+        let summary = engine.summary();
+        assert_eq!((summary.correct, summary.rearranged, summary.unfixable), (4, 2, 0));
+    }
+
+    #[test]
+    fn report_and_serve_conflict() {
+        assert!(Args::try_parse_from(["aoc_5", "-f", "input.txt", "--report", "json"]).is_ok());
+        assert!(Args::try_parse_from(["aoc_5", "--serve", "--report", "json"]).is_err());
+    }
+}
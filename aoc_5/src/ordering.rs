@@ -0,0 +1,270 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use util::file::GenericParseError;
+
+pub type Page = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OrderingRule {
+    pub before: Page,
+    pub after: Page,
+}
+
+impl std::str::FromStr for OrderingRule {
+    type Err = GenericParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split('|').collect();
+        if parts.len() != 2 {
+            return Err(GenericParseError::ValueError(format!("Invalid ordering rule: {}", s)))
+        }
+
+        Ok(OrderingRule{
+            before: parts[0].parse::<Page>()?,
+            after: parts[1].parse::<Page>()?,
+        })
+    }
+}
+
+impl fmt::Display for OrderingRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}|{}", self.before, self.after)
+    }
+}
+
+#[derive(Debug)]
+pub enum OrderingError {
+    // Pages in cycle order, where each page must come before the next (and the last before the first)
+    Cycle(Vec<Page>),
+
+    // Adjacent pages in the best-effort order with no rule between them, so they could go either way
+    Ambiguous{order: Vec<Page>, unordered: Vec<(Page, Page)>},
+
+    // Pages that appear more than once in the update. A rule between a repeated page and another
+    // page can hold for one copy and break for the other, so there's no single place to move it to.
+    Repeated(Vec<Page>),
+}
+
+impl fmt::Display for OrderingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let err_msg = match self {
+            OrderingError::Cycle(pages) => {
+                let cycle = pages.iter().chain(pages.first()).map(|page| page.to_string()).collect::<Vec<String>>();
+                format!("Rules are cyclic: {}", cycle.join(" -> "))
+            },
+            OrderingError::Ambiguous{unordered, ..} => {
+                let pairs = unordered.iter().map(|(a, b)| format!("{}/{}", a, b)).collect::<Vec<String>>();
+                format!("No rule orders pages {}", pairs.join(", "))
+            },
+            OrderingError::Repeated(pages) => {
+                let pages = pages.iter().map(|page| page.to_string()).collect::<Vec<String>>();
+                format!("Update repeats pages {}", pages.join(", "))
+            },
+        };
+
+        write!(f, "{}", err_msg)
+    }
+}
+
+impl std::error::Error for OrderingError {}
+
+impl OrderingError {
+    // An order that breaks none of the rules, if there is one, just not the only one
+    pub fn best_effort_order(&self) -> Option<&[Page]> {
+        match self {
+            OrderingError::Ambiguous{order, ..} => Some(order),
+            _ => None,
+        }
+    }
+}
+
+// A rule broken by an update: `rule.before` sits at `before_idx`, after `rule.after` at `after_idx`
+#[derive(Debug)]
+pub struct Violation {
+    pub rule: OrderingRule,
+    pub before_idx: usize,
+    pub after_idx: usize,
+}
+
+pub struct Rules {
+    successors: HashMap<Page, HashSet<Page>>, // Every page that must come after a given page
+}
+
+impl Rules {
+    pub fn new(rules: &[OrderingRule]) -> Rules {
+        let mut successors: HashMap<Page, HashSet<Page>> = HashMap::new();
+        for rule in rules {
+            successors.entry(rule.before).or_default().insert(rule.after);
+        }
+
+        Rules{successors}
+    }
+
+    // Both return whether the rule set actually changed
+    pub fn add(&mut self, rule: OrderingRule) -> bool {
+        self.successors.entry(rule.before).or_default().insert(rule.after)
+    }
+
+    pub fn remove(&mut self, rule: OrderingRule) -> bool {
+        self.successors.get_mut(&rule.before).is_some_and(|afters| afters.remove(&rule.after))
+    }
+
+    pub fn must_precede(&self, before: Page, after: Page) -> bool {
+        self.successors.get(&before).is_some_and(|afters| afters.contains(&after))
+    }
+
+    pub fn is_ordered(&self, pages: &[Page]) -> bool {
+        pages.iter().enumerate().all(|(i, &page)| {
+            pages[(i + 1)..].iter().all(|&later| !self.must_precede(later, page))
+        })
+    }
+
+    pub fn violations(&self, pages: &[Page]) -> Vec<Violation> {
+        let mut ret: Vec<Violation> = vec![];
+        for (after_idx, &after) in pages.iter().enumerate() {
+            for (before_idx, &before) in pages.iter().enumerate().skip(after_idx + 1) {
+                if self.must_precede(before, after) {
+                    ret.push(Violation{rule: OrderingRule{before, after}, before_idx, after_idx});
+                }
+            }
+        }
+
+        ret
+    }
+
+    // Kahn's algorithm over only the rules whose pages both appear in the update. The order is
+    // canonical only if it's unique, i.e. every adjacent pair in it is directly ordered by a rule.
+    pub fn canonical_order(&self, pages: &[Page]) -> Result<Vec<Page>, OrderingError> {
+        let mut in_update: HashSet<Page> = HashSet::new();
+        let repeated: BTreeSet<Page> = pages.iter().copied().filter(|&page| !in_update.insert(page)).collect();
+        if !repeated.is_empty() {
+            return Err(OrderingError::Repeated(repeated.into_iter().collect()));
+        }
+
+        let successors_in_update = |page: Page| -> Vec<Page> {
+            self.successors.get(&page)
+                .map(|afters| afters.iter().copied().filter(|after| in_update.contains(after)).collect())
+                .unwrap_or_default()
+        };
+
+        let mut in_degrees: HashMap<Page, usize> = in_update.iter().map(|&page| (page, 0)).collect();
+        for &page in in_update.iter() {
+            for after in successors_in_update(page) {
+                *in_degrees.get_mut(&after).unwrap() += 1;
+            }
+        }
+
+        // Smallest-first makes the best-effort order deterministic when it isn't unique
+        let mut ready: BTreeSet<Page> = in_degrees.iter().filter(|(_, &n)| n == 0).map(|(&page, _)| page).collect();
+        let mut order: Vec<Page> = vec![];
+        while let Some(page) = ready.pop_first() {
+            order.push(page);
+            for after in successors_in_update(page) {
+                let in_degree = in_degrees.get_mut(&after).unwrap();
+                *in_degree -= 1;
+                if *in_degree == 0 {
+                    ready.insert(after);
+                }
+            }
+        }
+
+        if order.len() < in_update.len() {
+            let remaining: HashSet<Page> = in_degrees.into_iter().filter(|&(_, n)| n > 0).map(|(page, _)| page).collect();
+            return Err(OrderingError::Cycle(self.find_cycle(&remaining)));
+        }
+
+        let unordered: Vec<(Page, Page)> = order.windows(2)
+            .filter(|pair| !self.must_precede(pair[0], pair[1]))
+            .map(|pair| (pair[0], pair[1]))
+            .collect();
+        if !unordered.is_empty() {
+            return Err(OrderingError::Ambiguous{order, unordered});
+        }
+
+        Ok(order)
+    }
+
+    // Every page left over after Kahn's algorithm has a predecessor that's also left over, so walking
+    // predecessors from any of them must eventually revisit a page
+    fn find_cycle(&self, remaining: &HashSet<Page>) -> Vec<Page> {
+        let predecessor = |page: Page| -> Page {
+            *remaining.iter().filter(|&&before| self.must_precede(before, page)).min().unwrap()
+        };
+
+        let mut walk: Vec<Page> = vec![*remaining.iter().min().unwrap()];
+        loop {
+            let next = predecessor(*walk.last().unwrap());
+            if let Some(idx) = walk.iter().position(|&page| page == next) {
+                let mut cycle = walk.split_off(idx);
+                cycle.reverse();
+                return cycle;
+            }
+            walk.push(next);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(text: &str) -> Rules {
+        Rules::new(&text.split_whitespace().map(|rule| rule.parse::<OrderingRule>().unwrap()).collect::<Vec<OrderingRule>>())
+    }
+
+    #[test]
+    fn rules_parse_and_display_the_same() {
+        assert_eq!("47|53".parse::<OrderingRule>().unwrap(), OrderingRule{before: 47, after: 53});
+        assert_eq!(OrderingRule{before: 47, after: 53}.to_string(), "47|53");
+        for text in ["47", "47|53|61", "47|", "|53", "x|53"] {
+            assert!(text.parse::<OrderingRule>().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn orders_by_the_rules_within_the_update() {
+        // 4 and 5 are in a cycle, but neither is in the update
+        let rules = rules("1|2 2|3 1|3 3|4 4|5 5|4");
+        assert_eq!(rules.canonical_order(&[3, 1, 2]).unwrap(), vec![1, 2, 3]);
+        assert!(rules.is_ordered(&[1, 2, 3]) && !rules.is_ordered(&[3, 1, 2]));
+
+        let violations = rules.violations(&[3, 1, 2]);
+        let broken: Vec<(OrderingRule, usize, usize)> = violations.iter().map(|v| (v.rule, v.before_idx, v.after_idx)).collect();
+        assert_eq!(broken, vec![(OrderingRule{before: 1, after: 3}, 1, 0), (OrderingRule{before: 2, after: 3}, 2, 0)]);
+    }
+
+    #[test]
+    fn reports_cycles_in_order() {
+        let rules = rules("1|2 2|3 3|1 3|4");
+        let Err(OrderingError::Cycle(cycle)) = rules.canonical_order(&[4, 3, 2, 1]) else { panic!() };
+        assert_eq!(cycle.len(), 3);
+        for (idx, &page) in cycle.iter().enumerate() {
+            assert!(rules.must_precede(page, cycle[(idx + 1) % cycle.len()]), "{:?}", cycle);
+        }
+        assert_eq!(OrderingError::Cycle(vec![1, 2, 3]).to_string(), "Rules are cyclic: 1 -> 2 -> 3 -> 1");
+    }
+
+    #[test]
+    fn reports_pages_no_rule_orders() {
+        let rules = rules("1|2 1|3");
+        let err = rules.canonical_order(&[3, 2, 1]).unwrap_err();
+        let OrderingError::Ambiguous{order, unordered} = &err else { panic!() };
+        assert_eq!((order.clone(), unordered.clone()), (vec![1, 2, 3], vec![(2, 3)]));
+        assert_eq!(err.best_effort_order(), Some(&[1, 2, 3][..]));
+        assert_eq!(err.to_string(), "No rule orders pages 2/3");
+
+        let cyclic = self::rules("1|2 2|1").canonical_order(&[1, 2]).unwrap_err();
+        assert_eq!(cyclic.best_effort_order(), None);
+    }
+
+    #[test]
+    fn adding_and_removing_report_changes() {
+        let mut rules = rules("1|2");
+        assert!(!rules.add(OrderingRule{before: 1, after: 2}));
+        assert!(rules.add(OrderingRule{before: 2, after: 1}));
+        assert!(rules.remove(OrderingRule{before: 1, after: 2}));
+        assert!(!rules.remove(OrderingRule{before: 1, after: 2}));
+        assert!(!rules.remove(OrderingRule{before: 7, after: 8}));
+        assert!(rules.must_precede(2, 1) && !rules.must_precede(1, 2));
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use super::ordering::{OrderingError, Page, Rules, Violation};
+
+pub struct Fix {
+    pub order: Vec<Page>,
+    pub swaps: usize, // Fewest exchanges of two pages
+    pub moves: usize, // Fewest removals of a page and re-insertions elsewhere
+}
+
+impl Fix {
+    fn new(pages: &[Page], order: Vec<Page>) -> Fix {
+        // Where each page of the update ends up in the fixed order (which never repeats a page)
+        let targets: HashMap<Page, usize> = order.iter().enumerate().map(|(idx, &page)| (page, idx)).collect();
+        let permutation: Vec<usize> = pages.iter().map(|page| targets[page]).collect();
+
+        // Each cycle of the permutation takes one fewer swap than its length to resolve
+        let mut seen = vec![false; permutation.len()];
+        let mut cycles: usize = 0;
+        for start in 0..permutation.len() {
+            if seen[start] {
+                continue;
+            }
+
+            cycles += 1;
+            let mut idx = start;
+            while !seen[idx] {
+                seen[idx] = true;
+                idx = permutation[idx];
+            }
+        }
+
+        // Pages in the longest increasing run of targets can stay put, everything else has to move
+        let mut tails: Vec<usize> = vec![];
+        for &target in permutation.iter() {
+            let pos = tails.partition_point(|&tail| tail < target);
+            if pos == tails.len() {
+                tails.push(target);
+            } else {
+                tails[pos] = target;
+            }
+        }
+
+        Fix{order, swaps: permutation.len() - cycles, moves: permutation.len() - tails.len()}
+    }
+}
+
+pub struct UpdateReport {
+    pub pages: Vec<Page>,
+    pub violations: Vec<Violation>,
+    pub fix: Result<Fix, OrderingError>,
+}
+
+impl UpdateReport {
+    pub fn new(pages: &[Page], rules: &Rules) -> UpdateReport {
+        UpdateReport{
+            pages: pages.to_vec(),
+            violations: rules.violations(pages),
+            fix: rules.canonical_order(pages).map(|order| Fix::new(pages, order)),
+        }
+    }
+
+    pub fn to_human(&self) -> String {
+        let mut lines: Vec<String> = vec![format!("Update {}:", join(&self.pages, ","))];
+        for violation in self.violations.iter() {
+            lines.push(format!(
+                "  violates {} ({} at position {}, {} at position {})",
+                violation.rule, violation.rule.before, violation.before_idx, violation.rule.after, violation.after_idx,
+            ));
+        }
+
+        match &self.fix {
+            Ok(fix) => lines.push(format!(
+                "  fixed order {} takes {} swap(s) or {} move(s)", join(&fix.order, ","), fix.swaps, fix.moves,
+            )),
+            Err(e) => lines.push(format!("  can't be fixed: {}", e)),
+        }
+
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> String {
+        let violations = self.violations.iter().map(|violation| format!(
+            "{{\"before\":{},\"after\":{},\"before_position\":{},\"after_position\":{}}}",
+            violation.rule.before, violation.rule.after, violation.before_idx, violation.after_idx,
+        )).collect::<Vec<String>>();
+
+        let fix = match &self.fix {
+            Ok(fix) => format!(
+                "\"fixed_order\":[{}],\"min_swaps\":{},\"min_moves\":{},\"error\":null",
+                join(&fix.order, ","), fix.swaps, fix.moves,
+            ),
+            Err(e) => format!(
+                "\"fixed_order\":null,\"min_swaps\":null,\"min_moves\":null,\"error\":\"{}\"",
+                e.to_string().replace('\\', "\\\\").replace('"', "\\\""),
+            ),
+        };
+
+        format!("{{\"pages\":[{}],\"violations\":[{}],{}}}", join(&self.pages, ","), violations.join(","), fix)
+    }
+}
+
+fn join(pages: &[Page], sep: &str) -> String {
+    pages.iter().map(|page| page.to_string()).collect::<Vec<String>>().join(sep)
+}
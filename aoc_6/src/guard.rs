@@ -0,0 +1,121 @@
+use clap::ValueEnum;
+
+// Directions are 0 = up, 1 = right, 2 = down, 3 = left, so turning right is adding 1 (mod 4)
+pub fn dir_name(dir: usize) -> &'static str {
+    match dir {
+        0 => "up",
+        1 => "right",
+        2 => "down",
+        3 => "left",
+        _ => "?",
+    }
+}
+
+// What the guard does on bumping into an obstacle
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TurnPolicy {
+    Right,
+    Left,
+    Alternating, // Right first, then left, then right...
+    Reverse,
+}
+
+impl TurnPolicy {
+    // `turns` is how many times the guard has already turned
+    pub fn turn(&self, dir: usize, turns: usize) -> usize {
+        let quarter_turns = match self {
+            TurnPolicy::Right => 1,
+            TurnPolicy::Left => 3,
+            TurnPolicy::Alternating => if turns.is_multiple_of(2) { 1 } else { 3 },
+            TurnPolicy::Reverse => 2,
+        };
+
+        (dir + quarter_turns) & 0b11
+    }
+
+    // How many different ways the next turn can go, which has to be part of the guard's state
+    // when checking for loops (only alternating turns depend on turn history)
+    pub fn phases(&self) -> usize {
+        match self {
+            TurnPolicy::Alternating => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Guard {
+    pub coord: (usize, usize),
+    pub dir: usize,
+}
+
+impl Guard {
+    pub fn from_char(c: char, coord: (usize, usize)) -> Option<Guard> {
+        let dir = match c {
+            '^' => 0,
+            '>' => 1,
+            'v' => 2,
+            '<' => 3,
+            _ => return None,
+        };
+
+        Some(Guard{coord, dir})
+    }
+}
+
+#[derive(Debug)]
+pub struct Turn {
+    pub coord: (usize, usize),
+    pub from_dir: usize,
+    pub to_dir: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Exited,
+    Looped,
+}
+
+// The guard entering a tile, moving in `dir` after `turns` turns so far
+#[derive(Clone, Copy, Debug)]
+pub struct Step {
+    pub coord: (usize, usize),
+    pub dir: usize,
+    pub turns: usize,
+}
+
+#[derive(Debug)]
+pub struct GuardPath {
+    pub steps: Vec<Step>, // Every tile in the order the guard walks it (up to the first repeat, if looping)
+    pub turns: Vec<Turn>,
+    pub outcome: Outcome,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies_turn_from_every_direction() {
+        for dir in 0..4 {
+            assert_eq!(TurnPolicy::Right.turn(dir, 0), (dir + 1) % 4);
+            assert_eq!(TurnPolicy::Left.turn(dir, 0), (dir + 3) % 4);
+            assert_eq!(TurnPolicy::Reverse.turn(dir, 5), (dir + 2) % 4);
+            assert_eq!(TurnPolicy::Alternating.turn(dir, 0), (dir + 1) % 4);
+            assert_eq!(TurnPolicy::Alternating.turn(dir, 1), (dir + 3) % 4);
+            assert_eq!(TurnPolicy::Alternating.turn(dir, 2), (dir + 1) % 4);
+        }
+
+        assert_eq!(TurnPolicy::Alternating.phases(), 2);
+        assert_eq!(TurnPolicy::Reverse.phases(), 1);
+    }
+
+    #[test]
+    fn guards_face_the_way_they_point() {
+        assert_eq!(Guard::from_char('^', (1, 2)), Some(Guard{coord: (1, 2), dir: 0}));
+        assert_eq!(Guard::from_char('>', (1, 2)).map(|guard| dir_name(guard.dir)), Some("right"));
+        assert_eq!(Guard::from_char('v', (1, 2)).map(|guard| dir_name(guard.dir)), Some("down"));
+        assert_eq!(Guard::from_char('<', (1, 2)).map(|guard| dir_name(guard.dir)), Some("left"));
+        assert_eq!(Guard::from_char('#', (1, 2)), None);
+    }
+}
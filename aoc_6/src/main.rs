@@ -1,5 +1,44 @@
-use util::res::Result;
 use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::Instant;
+use clap::{Parser, ValueEnum};
+use util::res::Result;
+use guard::{dir_name, Guard, GuardPath, Outcome, Step, Turn, TurnPolicy};
+use report::LoopReport;
+
+mod guard;
+mod report;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ReportFormat {
+    Grid,
+    Json,
+}
+
+#[derive(Parser, Debug)]
+struct Args {
+    #[arg(short, long)]
+    file: PathBuf,
+
+    #[arg(short, long, value_enum, default_value = "right")]
+    turn: TurnPolicy,
+
+    // Time the path-restricted loop search against trying every open tile
+    #[arg(short, long)]
+    bench: bool,
+
+    // Show the loop each part 2 obstacle traps the guard in
+    #[arg(short, long, value_enum)]
+    report: Option<ReportFormat>,
+
+    // Find the fewest added obstacles (up to this many) that trap each guard
+    #[arg(long)]
+    trap: Option<usize>,
+
+    // Find which single obstacle removals let each looping guard escape
+    #[arg(long)]
+    escape: bool,
+}
 
 #[derive(Clone, PartialEq)]
 enum GridSquare {
@@ -13,10 +52,49 @@ enum GridSquare {
 struct Grid {
     width: usize,
     height: usize,
-    guard_coord: (usize, usize),
+    guards: Vec<Guard>,
     squares: Vec<Vec<GridSquare>>,
 }
 
+// Flat bitset of (row, column, direction, turn phase) guard states
+struct StateSet {
+    bits: Vec<u64>,
+    width: usize,
+    phases: usize,
+}
+
+impl StateSet {
+    fn new(width: usize, height: usize, phases: usize) -> StateSet {
+        StateSet{bits: vec![0u64; (width * height * 4 * phases).div_ceil(64)], width, phases}
+    }
+
+    // Returns false if the state was already present
+    fn insert(&mut self, pos: (usize, usize), dir: usize, turns: usize) -> bool {
+        let idx = ((pos.0 * self.width + pos.1) * 4 + dir) * self.phases + turns % self.phases;
+        let mask = 1u64 << (idx & 63);
+        let present = self.bits[idx >> 6] & mask != 0;
+        self.bits[idx >> 6] |= mask;
+        !present
+    }
+
+    fn clear(&mut self) {
+        self.bits.fill(0);
+    }
+}
+
+// Obstacles added to or removed from the grid, applied on top of its precomputed jump pointers
+#[derive(Debug, Default)]
+struct ObstacleChanges {
+    added: Vec<(usize, usize)>,
+    removed: Vec<(usize, usize)>,
+}
+
+impl ObstacleChanges {
+    fn adding(coord: (usize, usize)) -> ObstacleChanges {
+        ObstacleChanges{added: vec![coord], removed: vec![]}
+    }
+}
+
 struct ObstacleUpdate {
     update_coord: (usize, usize),
     dir: usize,
@@ -43,10 +121,10 @@ impl GridSquare {
 }
 
 impl Grid {
-    fn from_lines(lines: &Vec<String>) -> Result<Grid> {
+    fn from_lines(lines: &[String]) -> Result<Grid> {
         let width = lines[0].len();
         let height = lines.len();
-        let mut guard_coord: Option<(usize, usize)> = None;
+        let mut guards: Vec<Guard> = vec![];
         let mut squares = vec![vec![GridSquare::Open([None, None, None, None]); width]; height];
 
         for row_idx in 0..height {
@@ -60,23 +138,17 @@ impl Grid {
                             squares[update_coord.0][update_coord.1].set_next_open(update_coord, update.dir, update.stop_coord);
                         }
                     },
-                    '^' => {
-                        if guard_coord.is_some() {
-                            return Err(From::from("More than 1 guard start position found".to_owned()));
-                        }
-
-                        guard_coord = Some((row_idx, col_idx));
-                    }
+                    '^' | '>' | 'v' | '<' => guards.extend(Guard::from_char(c, (row_idx, col_idx))),
                     _ => {}, // Nothing to do for open spaces
                 }
             }
         }
 
-        if guard_coord.is_none() {
+        if guards.is_empty() {
             return Err(From::from("No guard start position found".to_owned()));
         }
 
-        Ok(Grid{width, height, guard_coord: guard_coord.unwrap(), squares})
+        Ok(Grid{width, height, guards, squares})
     }
 
     fn get_updates_for_obstacle(width: usize, height: usize, obstacle_coords: (usize, usize)) -> Vec<ObstacleUpdate> {
@@ -106,36 +178,271 @@ impl Grid {
         updates
     }
 
-    fn get_guard_path_size(&self) -> usize {
-        let mut visited: HashSet<(usize, usize)> = HashSet::new();
-        let mut guard_pos = self.guard_coord;
-        let mut guard_dir = 0; // up
+    fn walk(&self, guard: Guard, policy: TurnPolicy, changes: &ObstacleChanges) -> GuardPath {
+        let mut seen_states = StateSet::new(self.width, self.height, policy.phases());
+        let mut steps: Vec<Step> = vec![];
+        let mut turns: Vec<Turn> = vec![];
+        let mut guard_pos = guard.coord;
+        let mut guard_dir = guard.dir;
+        if self.is_blocked(guard_pos, changes) {
+            return GuardPath{steps, turns, outcome: Outcome::Exited}; // guard can't start on an obstacle
+        }
+
         loop {
-            let next_guard_pos = match self.squares[guard_pos.0][guard_pos.1] {
-                GridSquare::Open(coords) => coords[guard_dir],
-                GridSquare::Obstacle => return 0 // guard can't start on an obstacle
-            };
+            if !seen_states.insert(guard_pos, guard_dir, turns.len()) {
+                return GuardPath{steps, turns, outcome: Outcome::Looped};
+            }
+
+            let next_guard_pos = self.next_stop(guard_pos, guard_dir, changes);
 
-            // Add visited states
-            visited.extend(self.get_tile_range(guard_pos, guard_dir, next_guard_pos));
+            // Add visited tiles in walking order, skipping the turn point we're standing on (already added)
+            let mut range = self.get_tile_range(guard_pos, guard_dir, next_guard_pos);
+            if guard_dir == 0 || guard_dir == 3 {
+                range.reverse();
+            }
+            let skip = if steps.is_empty() { 0 } else { 1 };
+            steps.extend(range.into_iter().skip(skip).map(|coord| Step{coord, dir: guard_dir, turns: turns.len()}));
 
             // Decide whether we've exited or not
             match next_guard_pos {
                 Some(pos) => {
+                    let next_dir = policy.turn(guard_dir, turns.len());
+                    turns.push(Turn{coord: pos, from_dir: guard_dir, to_dir: next_dir});
                     guard_pos = pos;
-                    guard_dir = (guard_dir + 1) & 0b11;
+                    guard_dir = next_dir;
                 },
                 None => break
             }
         }
 
+        GuardPath{steps, turns, outcome: Outcome::Exited}
+    }
+
+    fn get_guard_path_size(&self, policy: TurnPolicy) -> usize {
+        let visited: HashSet<(usize, usize)> = self.guards.iter()
+            .flat_map(|&guard| self.walk(guard, policy, &ObstacleChanges::default()).steps.into_iter().map(|step| step.coord))
+            .collect();
+
         visited.len()
     }
 
-    fn does_guard_loop_with_added_obstacle(&self, obstacle_coords: (usize, usize)) -> bool {
-        let mut seen_states: HashSet<(usize, usize, usize)> = HashSet::new();
-        let mut guard_pos = self.guard_coord;
-        let mut guard_dir = 0; // up
+    fn step(&self, pos: (usize, usize), dir: usize) -> Option<(usize, usize)> {
+        match dir {
+            0 if pos.0 > 0 => Some((pos.0 - 1, pos.1)),
+            1 if pos.1 + 1 < self.width => Some((pos.0, pos.1 + 1)),
+            2 if pos.0 + 1 < self.height => Some((pos.0 + 1, pos.1)),
+            3 if pos.1 > 0 => Some((pos.0, pos.1 - 1)),
+            _ => None,
+        }
+    }
+
+    fn is_blocked(&self, pos: (usize, usize), changes: &ObstacleChanges) -> bool {
+        if changes.added.contains(&pos) {
+            return true;
+        }
+
+        self.squares[pos.0][pos.1] == GridSquare::Obstacle && !changes.removed.contains(&pos)
+    }
+
+    // Where the guard stops moving in `dir` from `pos` with obstacles added or removed, using the
+    // precomputed jump pointers. Jumps ending at a removed obstacle carry on from the far side of it,
+    // then any added obstacle in the way cuts the whole run short.
+    fn next_stop(&self, pos: (usize, usize), dir: usize, changes: &ObstacleChanges) -> Option<(usize, usize)> {
+        let mut from = pos;
+        let stop = loop {
+            let reach = match self.squares[from.0][from.1] {
+                GridSquare::Open(coords) => coords[dir],
+                GridSquare::Obstacle => Some(from), // Standing where a removed obstacle was, no pointers to follow
+            };
+
+            let Some(reach) = reach else { break None };
+            match self.step(reach, dir) {
+                Some(next) if self.squares[next.0][next.1] == GridSquare::Obstacle && !changes.removed.contains(&next) => break Some(reach),
+                Some(next) => from = next,
+                None => break None,
+            }
+        };
+
+        // Nearest added obstacle on the run, if any
+        let blocking = changes.added.iter().filter(|&&obstacle| match dir {
+            0 => obstacle.1 == pos.1 && obstacle.0 < pos.0 && stop.is_none_or(|stop| obstacle.0 >= stop.0),
+            1 => obstacle.0 == pos.0 && obstacle.1 > pos.1 && stop.is_none_or(|stop| obstacle.1 <= stop.1),
+            2 => obstacle.1 == pos.1 && obstacle.0 > pos.0 && stop.is_none_or(|stop| obstacle.0 <= stop.0),
+            3 => obstacle.0 == pos.0 && obstacle.1 < pos.1 && stop.is_none_or(|stop| obstacle.1 >= stop.1),
+            _ => false,
+        }).min_by_key(|&&obstacle| pos.0.abs_diff(obstacle.0) + pos.1.abs_diff(obstacle.1));
+
+        match (blocking, dir) {
+            (None, _) => stop,
+            (Some(&obstacle), 0) => Some((obstacle.0 + 1, obstacle.1)),
+            (Some(&obstacle), 1) => Some((obstacle.0, obstacle.1 - 1)),
+            (Some(&obstacle), 2) => Some((obstacle.0 - 1, obstacle.1)),
+            (Some(&obstacle), _) => Some((obstacle.0, obstacle.1 + 1)),
+        }
+    }
+
+    fn does_guard_loop_from(&self, start: Step, policy: TurnPolicy, changes: &ObstacleChanges, seen_states: &mut StateSet) -> bool {
+        seen_states.clear();
+        let (mut guard_pos, mut guard_dir, mut turns) = (start.coord, start.dir, start.turns);
+        loop {
+            if !seen_states.insert(guard_pos, guard_dir, turns) {
+                return true; // we're in a loop!
+            }
+
+            match self.next_stop(guard_pos, guard_dir, changes) {
+                Some(pos) => {
+                    guard_pos = pos;
+                    guard_dir = policy.turn(guard_dir, turns);
+                    turns += 1;
+                },
+                None => return false
+            }
+        }
+    }
+
+    // The states (at the start of each straight run) making up the loop the guard ends up in with the
+    // extra obstacle, starting from the first one the guard reaches, or None if the guard gets out.
+    // Also returns where the guard first steps onto the loop, which can be partway along one of its
+    // runs (or the guard's starting tile).
+    fn trace_loop(&self, guard: Guard, policy: TurnPolicy, obstacle_coords: (usize, usize)) -> Option<(Step, Vec<Step>)> {
+        let changes = ObstacleChanges::adding(obstacle_coords);
+        let mut seen_states = StateSet::new(self.width, self.height, policy.phases());
+        let mut states: Vec<Step> = vec![];
+        let (mut guard_pos, mut guard_dir, mut turns) = (guard.coord, guard.dir, 0usize);
+        loop {
+            if !seen_states.insert(guard_pos, guard_dir, turns) {
+                let phases = policy.phases();
+                let entry = states.iter().position(|state| {
+                    state.coord == guard_pos && state.dir == guard_dir && state.turns % phases == turns % phases
+                }).unwrap();
+                let cycle = states.split_off(entry);
+                return Some((self.loop_entry(&states, &cycle, policy), cycle));
+            }
+            states.push(Step{coord: guard_pos, dir: guard_dir, turns});
+
+            match self.next_stop(guard_pos, guard_dir, &changes) {
+                Some(pos) => {
+                    guard_pos = pos;
+                    guard_dir = policy.turn(guard_dir, turns);
+                    turns += 1;
+                },
+                None => return None
+            }
+        }
+    }
+
+    // Tiles the guard first reaches along its route with the given changes (never its start), each
+    // paired with the step just before, where the route would first differ if an obstacle went there
+    fn first_visits(&self, guard: Guard, path: &GuardPath) -> Vec<((usize, usize), Step)> {
+        let mut on_path = vec![vec![false; self.width]; self.height];
+        on_path[guard.coord.0][guard.coord.1] = true;
+
+        path.steps.windows(2).filter_map(|pair| {
+            let (prev, step) = (pair[0], pair[1]);
+            let (row, col) = step.coord;
+            if on_path[row][col] {
+                return None;
+            }
+            on_path[row][col] = true;
+
+            Some((step.coord, Step{coord: prev.coord, dir: step.dir, turns: step.turns}))
+        }).collect()
+    }
+
+    // An obstacle can only change the guard's route if it's somewhere on that route, and the route up
+    // to the obstacle is unchanged, so each check resumes from the step before the guard first reaches it
+    fn get_loop_obstacles(&self, policy: TurnPolicy) -> Vec<(usize, usize)> {
+        let mut traps = vec![vec![false; self.width]; self.height];
+        let mut seen_states = StateSet::new(self.width, self.height, policy.phases());
+        for &guard in self.guards.iter() {
+            let path = self.walk(guard, policy, &ObstacleChanges::default());
+            for ((row, col), start) in self.first_visits(guard, &path) {
+                if !traps[row][col] && self.does_guard_loop_from(start, policy, &ObstacleChanges::adding((row, col)), &mut seen_states) {
+                    traps[row][col] = true;
+                }
+            }
+
+            // A guard that already loops keeps looping no matter what's added off its route
+            if path.outcome == Outcome::Looped {
+                let mut on_path = vec![vec![false; self.width]; self.height];
+                path.steps.iter().for_each(|step| on_path[step.coord.0][step.coord.1] = true);
+                for row in 0..self.height {
+                    for col in 0..self.width {
+                        traps[row][col] |= !on_path[row][col] && self.squares[row][col] != GridSquare::Obstacle;
+                    }
+                }
+            }
+        }
+
+        for guard in self.guards.iter() {
+            traps[guard.coord.0][guard.coord.1] = false;
+        }
+
+        (0..self.height).flat_map(|row| (0..self.width).map(move |col| (row, col)))
+            .filter(|&(row, col)| traps[row][col])
+            .collect()
+    }
+
+    // Fewest obstacles (up to `max_added`) that trap the guard in a loop, found by iterative deepening.
+    // Only tiles on the guard's current route are worth trying at each level.
+    fn min_obstacles_to_trap(&self, guard: Guard, policy: TurnPolicy, max_added: usize) -> Option<Vec<(usize, usize)>> {
+        let mut changes = ObstacleChanges::default();
+        let mut seen_states = StateSet::new(self.width, self.height, policy.phases());
+        (0..=max_added).find(|&budget| self.trap_within(guard, policy, budget, &mut changes, &mut seen_states))
+            .map(|_| changes.added)
+    }
+
+    fn trap_within(&self, guard: Guard, policy: TurnPolicy, budget: usize, changes: &mut ObstacleChanges, seen_states: &mut StateSet) -> bool {
+        let path = self.walk(guard, policy, changes);
+        if path.outcome == Outcome::Looped {
+            return true;
+        }
+        if budget == 0 {
+            return false;
+        }
+
+        for (coord, start) in self.first_visits(guard, &path) {
+            changes.added.push(coord);
+            let trapped = if budget == 1 {
+                self.does_guard_loop_from(start, policy, changes, seen_states)
+            } else {
+                self.trap_within(guard, policy, budget - 1, changes, seen_states)
+            };
+            if trapped {
+                return true;
+            }
+            changes.added.pop();
+        }
+
+        false
+    }
+
+    // Obstacles whose removal alone lets a looping guard get out. Only the obstacles the guard
+    // actually bumps into can change its route.
+    fn escaping_removals(&self, guard: Guard, policy: TurnPolicy) -> Vec<(usize, usize)> {
+        let path = self.walk(guard, policy, &ObstacleChanges::default());
+        if path.outcome == Outcome::Exited {
+            return vec![];
+        }
+
+        let mut bumped: Vec<(usize, usize)> = path.turns.iter()
+            .filter_map(|turn| self.step(turn.coord, turn.from_dir))
+            .filter(|&coord| self.squares[coord.0][coord.1] == GridSquare::Obstacle)
+            .collect();
+        bumped.sort();
+        bumped.dedup();
+
+        bumped.into_iter().filter(|&obstacle| {
+            let changes = ObstacleChanges{added: vec![], removed: vec![obstacle]};
+            self.walk(guard, policy, &changes).outcome == Outcome::Exited
+        }).collect()
+    }
+
+    fn does_guard_loop_with_added_obstacle(&self, guard: Guard, policy: TurnPolicy, obstacle_coords: (usize, usize)) -> bool {
+        let mut seen_states: HashSet<(usize, usize, usize, usize)> = HashSet::new();
+        let mut guard_pos = guard.coord;
+        let mut guard_dir = guard.dir;
+        let mut turns = 0usize;
 
         // Calculate pathfinding overrides when adding this one obstacle
         let obstacle_updates = Grid::get_updates_for_obstacle(self.width, self.height, obstacle_coords);
@@ -148,7 +455,7 @@ impl Grid {
         }).collect();
 
         loop {
-            if !seen_states.insert((guard_pos.0, guard_pos.1, guard_dir)) {
+            if !seen_states.insert((guard_pos.0, guard_pos.1, guard_dir, turns % policy.phases())) {
                 return true; // we're in a loop!
             }
 
@@ -162,7 +469,8 @@ impl Grid {
             match next_guard_pos {
                 Some(pos) => {
                     guard_pos = pos;
-                    guard_dir = (guard_dir + 1) & 0b11;
+                    guard_dir = policy.turn(guard_dir, turns);
+                    turns += 1;
                 },
                 None => break
             }
@@ -171,14 +479,15 @@ impl Grid {
         false
     }
 
-    fn get_num_loops_with_added_obstacle(&self) -> usize {
+    // Counts obstacle positions that trap at least one of the guards in a loop, trying every open tile
+    fn get_num_loops_with_added_obstacle_brute_force(&self, policy: TurnPolicy) -> usize {
         let mut count = 0usize;
         for row in 0..self.height {
             for col in 0..self.width {
-                if (row, col) == self.guard_coord { continue }
+                if self.guards.iter().any(|guard| guard.coord == (row, col)) { continue }
                 if self.squares[row][col] == GridSquare::Obstacle { continue }
 
-                if self.does_guard_loop_with_added_obstacle((row, col)) {
+                if self.guards.iter().any(|&guard| self.does_guard_loop_with_added_obstacle(guard, policy, (row, col))) {
                     count += 1
                 }
             } 
@@ -187,6 +496,37 @@ impl Grid {
         count
     }
 
+    // The first tile of the walk up to the loop at which the guard is in a state that's part of the
+    // loop, from where it can only go on around it
+    fn loop_entry(&self, before: &[Step], cycle: &[Step], policy: TurnPolicy) -> Step {
+        let phases = policy.phases();
+        let on_loop: HashSet<((usize, usize), usize, usize)> = cycle.iter().zip(cycle.iter().cycle().skip(1))
+            .flat_map(|(state, next)| {
+                self.walk_tiles(state.coord, state.dir, next.coord).into_iter().map(|coord| (coord, state.dir, state.turns % phases))
+            })
+            .collect();
+
+        for (state, next) in before.iter().zip(before.iter().skip(1).chain(cycle.iter().take(1))) {
+            for coord in self.walk_tiles(state.coord, state.dir, next.coord) {
+                if on_loop.contains(&(coord, state.dir, state.turns % phases)) {
+                    return Step{coord, ..*state};
+                }
+            }
+        }
+
+        cycle[0]
+    }
+
+    // Tiles from `start` to `end` in the order a guard facing `dir` walks them
+    fn walk_tiles(&self, start: (usize, usize), dir: usize, end: (usize, usize)) -> Vec<(usize, usize)> {
+        let mut tiles = self.get_tile_range(start, dir, Some(end));
+        if dir == 0 || dir == 3 {
+            tiles.reverse();
+        }
+
+        tiles
+    }
+
     fn get_tile_range(&self, start: (usize, usize), dir: usize, end: Option<(usize, usize)>) -> Vec<(usize, usize)> {
         match dir {
             0 => { // up
@@ -210,26 +550,231 @@ impl Grid {
     }
 }
 
-fn part1(grid: &Grid) {
-    let guard_path_size = grid.get_guard_path_size();
+fn part1(grid: &Grid, policy: TurnPolicy) {
+    for (idx, &guard) in grid.guards.iter().enumerate() {
+        let path = grid.walk(guard, policy, &ObstacleChanges::default());
+        let turns = path.turns.iter()
+            .map(|turn| format!("({}, {}) {}->{}", turn.coord.0, turn.coord.1, dir_name(turn.from_dir), dir_name(turn.to_dir)))
+            .collect::<Vec<String>>();
+        let turns = if turns.is_empty() { "nowhere".to_owned() } else { turns.join(", ") };
+        println!(
+            "Guard {} starting at ({}, {}) facing {} {} after walking {} tiles, turning at: {}",
+            idx, guard.coord.0, guard.coord.1, dir_name(guard.dir),
+            if path.outcome == Outcome::Looped { "loops" } else { "exits" }, path.steps.len(), turns,
+        );
+    }
+
+    let guard_path_size = grid.get_guard_path_size(policy);
 
     println!("# of tiles visited by guard: {}", guard_path_size);
 }
 
-fn part2(grid: &Grid) {
-    let num_loops = grid.get_num_loops_with_added_obstacle();
+// With `draw_loops`, also draws the loop each obstacle traps a guard in
+fn part2(grid: &Grid, policy: TurnPolicy, draw_loops: bool) {
+    let obstacles = grid.get_loop_obstacles(policy);
+    if draw_loops {
+        for report in loop_reports(grid, policy, &obstacles) {
+            println!("{}\n", report.to_grid(grid));
+        }
+    }
+
+    println!("# loops with 1 added obstacle: {}", obstacles.len());
+}
+
+fn bench(grid: &Grid, policy: TurnPolicy) {
+    let start = Instant::now();
+    let brute_force_loops = grid.get_num_loops_with_added_obstacle_brute_force(policy);
+    let brute_force_time = start.elapsed();
+
+    let start = Instant::now();
+    let path_loops = grid.get_loop_obstacles(policy).len();
+    let path_time = start.elapsed();
+
+    println!("Every open tile: {} loops in {:?}", brute_force_loops, brute_force_time);
+    println!("Guard path only: {} loops in {:?} ({:.1}x faster)", path_loops, path_time, brute_force_time.as_secs_f64() / path_time.as_secs_f64());
+}
+
+// One per guard each obstacle traps
+fn loop_reports(grid: &Grid, policy: TurnPolicy, obstacles: &[(usize, usize)]) -> Vec<LoopReport> {
+    obstacles.iter().flat_map(|&obstacle| {
+        grid.guards.iter().enumerate().filter_map(move |(guard_idx, &guard)| {
+            grid.trace_loop(guard, policy, obstacle).map(|(entry, cycle)| LoopReport{obstacle, guard: guard_idx, entry, cycle})
+        })
+    }).collect()
+}
+
+fn format_coords(coords: &[(usize, usize)]) -> String {
+    coords.iter().map(|(row, col)| format!("({}, {})", row, col)).collect::<Vec<String>>().join(", ")
+}
+
+fn trap(grid: &Grid, policy: TurnPolicy, max_added: usize) {
+    for (idx, &guard) in grid.guards.iter().enumerate() {
+        match grid.min_obstacles_to_trap(guard, policy, max_added) {
+            Some(added) if added.is_empty() => println!("Guard {} already loops", idx),
+            Some(added) => println!("Guard {} is trapped by adding {} obstacle(s) at: {}", idx, added.len(), format_coords(&added)),
+            None => println!("Guard {} can't be trapped with up to {} added obstacle(s)", idx, max_added),
+        }
+    }
+}
 
-    println!("# loops with 1 added obstacle: {}", num_loops);
+fn escape(grid: &Grid, policy: TurnPolicy) {
+    for (idx, &guard) in grid.guards.iter().enumerate() {
+        if grid.walk(guard, policy, &ObstacleChanges::default()).outcome == Outcome::Exited {
+            println!("Guard {} already escapes", idx);
+            continue;
+        }
+
+        let removals = grid.escaping_removals(guard, policy);
+        if removals.is_empty() {
+            println!("No single obstacle removal lets guard {} escape", idx);
+        } else {
+            println!("Guard {} escapes if any one of these obstacles is removed: {}", idx, format_coords(&removals));
+        }
+    }
 }
 
 fn main() -> Result<()> {
-    let file_path = util::file::get_input_file_path();
-    let lines = util::file::read_lines_raw(file_path)?;
+    let args = Args::parse();
+    let lines = util::file::read_lines_raw(args.file)?;
 
     let grid = Grid::from_lines(&lines)?;
 
-    part1(&grid);
-    part2(&grid);
+    if args.bench {
+        bench(&grid, args.turn);
+        return Ok(());
+    }
+
+    if args.trap.is_some() || args.escape {
+        if let Some(max_added) = args.trap {
+            trap(&grid, args.turn, max_added);
+        }
+        if args.escape {
+            escape(&grid, args.turn);
+        }
+        return Ok(());
+    }
+
+    if let Some(ReportFormat::Json) = args.report {
+        let reports = loop_reports(&grid, args.turn, &grid.get_loop_obstacles(args.turn));
+        println!("[{}]", reports.iter().map(|report| report.to_json()).collect::<Vec<String>>().join(","));
+        return Ok(());
+    }
+
+    part1(&grid, args.turn);
+    part2(&grid, args.turn, args.report.is_some());
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(text: &str) -> Grid {
+        Grid::from_lines(&text.lines().map(|line| line.to_owned()).collect::<Vec<String>>()).unwrap()
+    }
+
+    // Obstacles around a loop of tiles from (1, 1) to (4, 4), walked clockwise
+    const LOOP: [&str; 6] = [
+        ".#....",
+        ".....#",
+        "......",
+        "......",
+        "#.....",
+        "....#.",
+    ];
+
+    fn loop_with_guard(guard: (usize, usize), c: char) -> Grid {
+        let lines: Vec<String> = LOOP.iter().enumerate().map(|(row, line)| {
+            line.chars().enumerate().map(|(col, square)| if (row, col) == guard { c } else { square }).collect()
+        }).collect();
+        grid(&lines.join("\n"))
+    }
+
+    #[test]
+    fn entry_is_the_start_when_the_guard_starts_on_the_loop() {
+        let grid = loop_with_guard((3, 1), '^');
+        let (entry, cycle) = grid.trace_loop(grid.guards[0], TurnPolicy::Right, (5, 0)).unwrap();
+        assert_eq!((entry.coord, entry.dir), ((3, 1), 0));
+        assert_eq!((cycle[0].coord, cycle[0].dir), ((1, 1), 1));
+    }
+
+    #[test]
+    fn entry_can_be_partway_along_a_run() {
+        let grid = loop_with_guard((4, 5), '<');
+        let (entry, cycle) = grid.trace_loop(grid.guards[0], TurnPolicy::Right, (5, 0)).unwrap();
+        assert_eq!((entry.coord, entry.dir), ((4, 4), 3));
+        assert_eq!((cycle[0].coord, cycle[0].dir), ((4, 1), 0));
+
+        let report = LoopReport{obstacle: (5, 0), guard: 0, entry, cycle};
+        assert_eq!(report.length(), 12);
+        assert!(report.to_json().contains("\"entry\":{\"row\":4,\"col\":4,\"dir\":\"left\"}"));
+    }
+
+    const EXAMPLE: &str = "....#.....
+.........#
+..........
+..#.......
+.......#..
+..........
+.#..^.....
+........#.
+#.........
+......#...";
+
+    #[test]
+    fn example_answers() {
+        let grid = grid(EXAMPLE);
+        assert_eq!(grid.get_guard_path_size(TurnPolicy::Right), 41);
+        assert_eq!(grid.get_loop_obstacles(TurnPolicy::Right).len(), 6);
+    }
+
+    #[test]
+    fn path_restricted_search_matches_trying_every_tile() {
+        let grids = [grid(EXAMPLE), loop_with_guard((4, 5), '<'), grid("..#..\n#...#\n..^..\n.#...\n...#.\n.v..<")];
+        for grid in grids.iter() {
+            for policy in [TurnPolicy::Right, TurnPolicy::Left, TurnPolicy::Alternating, TurnPolicy::Reverse] {
+                assert_eq!(grid.get_loop_obstacles(policy).len(), grid.get_num_loops_with_added_obstacle_brute_force(policy), "{:?}", policy);
+            }
+        }
+    }
+
+    #[test]
+    fn trapping_adds_the_fewest_obstacles() {
+        let grid = grid(EXAMPLE);
+        let guard = grid.guards[0];
+        assert_eq!(grid.min_obstacles_to_trap(guard, TurnPolicy::Right, 0), None);
+
+        let added = grid.min_obstacles_to_trap(guard, TurnPolicy::Right, 2).unwrap();
+        assert_eq!(added.len(), 1);
+        let changes = ObstacleChanges{added, removed: vec![]};
+        assert_eq!(grid.walk(guard, TurnPolicy::Right, &changes).outcome, Outcome::Looped);
+
+        let grid = loop_with_guard((3, 1), '^');
+        assert_eq!(grid.min_obstacles_to_trap(grid.guards[0], TurnPolicy::Right, 2), Some(vec![]));
+    }
+
+    #[test]
+    fn escaping_needs_a_bumped_obstacle_removed() {
+        let looping = loop_with_guard((3, 1), '^');
+        assert_eq!(looping.escaping_removals(looping.guards[0], TurnPolicy::Right), vec![(0, 1), (1, 5), (4, 0), (5, 4)]);
+
+        // Already gets out, so there's nothing to remove
+        let exiting = grid(EXAMPLE);
+        assert!(exiting.escaping_removals(exiting.guards[0], TurnPolicy::Right).is_empty());
+    }
+
+    #[test]
+    fn example_loop_entries_are_on_their_loops() {
+        let grid = grid(EXAMPLE);
+        let obstacles = grid.get_loop_obstacles(TurnPolicy::Right);
+        assert_eq!(obstacles.len(), 6);
+        for obstacle in obstacles {
+            let (entry, cycle) = grid.trace_loop(grid.guards[0], TurnPolicy::Right, obstacle).unwrap();
+            let on_loop = cycle.iter().zip(cycle.iter().cycle().skip(1)).any(|(state, next)| {
+                state.dir == entry.dir && grid.walk_tiles(state.coord, state.dir, next.coord).contains(&entry.coord)
+            });
+            assert!(on_loop);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use super::guard::{dir_name, Step};
+use super::{Grid, GridSquare};
+
+// An added obstacle that traps a guard, and the loop it's trapped in
+pub struct LoopReport {
+    pub obstacle: (usize, usize),
+    pub guard: usize,
+    pub entry: Step, // First step of the guard's walk that's on the loop
+    pub cycle: Vec<Step>, // Straight runs making up the loop, from the first one the guard reaches
+}
+
+impl LoopReport {
+    // Number of tiles walked to go around the loop once
+    pub fn length(&self) -> usize {
+        self.cycle.iter().zip(self.cycle.iter().cycle().skip(1)).map(|(state, next)| {
+            state.coord.0.abs_diff(next.coord.0) + state.coord.1.abs_diff(next.coord.1)
+        }).sum()
+    }
+
+    fn summary(&self) -> String {
+        let entry = self.entry;
+        format!(
+            "Obstacle at ({}, {}) traps guard {}: enters a loop of {} tiles ({} runs) at ({}, {}) facing {}",
+            self.obstacle.0, self.obstacle.1, self.guard, self.length(), self.cycle.len(),
+            entry.coord.0, entry.coord.1, dir_name(entry.dir),
+        )
+    }
+
+    // Loop tiles are drawn like the puzzle does (`|`, `-`, and `+` where runs cross or turn), with the
+    // added obstacle as `O` and the loop entry as `@`
+    pub fn to_grid(&self, grid: &Grid) -> String {
+        let mut chars: Vec<Vec<char>> = grid.squares.iter().map(|row| {
+            row.iter().map(|square| if *square == GridSquare::Obstacle { '#' } else { '.' }).collect()
+        }).collect();
+
+        for (state, next) in self.cycle.iter().zip(self.cycle.iter().cycle().skip(1)) {
+            let c = if state.dir % 2 == 0 { '|' } else { '-' };
+            for (row, col) in grid.get_tile_range(state.coord, state.dir, Some(next.coord)) {
+                chars[row][col] = match chars[row][col] {
+                    '.' => c,
+                    existing if existing == c => c,
+                    _ => '+',
+                };
+            }
+            chars[next.coord.0][next.coord.1] = '+';
+        }
+
+        let entry = self.entry.coord;
+        chars[entry.0][entry.1] = '@';
+        chars[self.obstacle.0][self.obstacle.1] = 'O';
+
+        let rows = chars.into_iter().map(|row| row.into_iter().collect::<String>()).collect::<Vec<String>>();
+        format!("{}\n{}", self.summary(), rows.join("\n"))
+    }
+
+    pub fn to_json(&self) -> String {
+        let entry = self.entry;
+        let states = self.cycle.iter().map(|state| format!(
+            "{{\"row\":{},\"col\":{},\"dir\":\"{}\"}}", state.coord.0, state.coord.1, dir_name(state.dir),
+        )).collect::<Vec<String>>();
+
+        format!(
+            "{{\"obstacle\":[{},{}],\"guard\":{},\"entry\":{{\"row\":{},\"col\":{},\"dir\":\"{}\"}},\"length\":{},\"states\":[{}]}}",
+            self.obstacle.0, self.obstacle.1, self.guard, entry.coord.0, entry.coord.1, dir_name(entry.dir),
+            self.length(), states.join(","),
+        )
+    }
+}
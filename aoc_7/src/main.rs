@@ -1,5 +1,37 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use clap::Parser;
 use util::res::Result;
 use util::file::GenericParseError;
+use operator::Operator;
+use solver::{Solver, SolverKind};
+
+mod operator;
+mod solver;
+
+#[derive(Parser, Debug)]
+struct Args {
+    #[arg(short, long)]
+    file: PathBuf,
+
+    // Solve with this comma-separated list of operators instead of the two puzzle parts
+    // (e.g. `add,mul,concat2`, see operator::from_name for every name)
+    #[arg(short, long, value_delimiter = ',')]
+    operators: Option<Vec<String>>,
+
+    #[arg(short, long, value_enum, default_value = "backward")]
+    solver: SolverKind,
+
+    // Time both solvers against each other, checking that they find exactly the same solutions
+    #[arg(short, long)]
+    bench: bool,
+
+    // List every solution to the equations that have more than one, instead of just counting them
+    #[arg(short, long)]
+    ambiguous: bool,
+}
 
 struct CalibrationEquation {
     result: u64,
@@ -7,19 +39,24 @@ struct CalibrationEquation {
 }
 
 #[derive(Clone)]
-enum Operation {
-    Add,
-    Multiply,
-    Concat,
-}
-
-#[derive(Clone)]
-struct CalibrationResult {
+struct CalibrationResult<'a> {
     operands: Vec<u64>,
-    operators: Vec<Operation>,
+    operators: Vec<&'a dyn Operator>,
     result: u64,
 }
 
+// Infix, evaluated left to right (e.g. `81 + 40 * 27`)
+impl fmt::Display for CalibrationResult<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.operands[0])?;
+        for (operator, operand) in self.operators.iter().zip(self.operands.iter().skip(1)) {
+            write!(f, " {} {}", operator.symbol(), operand)?;
+        }
+
+        Ok(())
+    }
+}
+
 impl std::str::FromStr for CalibrationEquation {
     type Err = GenericParseError;
 
@@ -33,15 +70,27 @@ impl std::str::FromStr for CalibrationEquation {
                     }).collect::<std::result::Result<Vec<u64>, Self::Err>>()?
                 })
             },
-            None => Err(GenericParseError::ValueError(format!("Invalid equation: {}", s).to_owned()))
+            None => Err(GenericParseError::ValueError(format!("Invalid equation: {}", s)))
         }
     }
 }
 
 impl CalibrationEquation {
-    fn get_calibration_result(&self, allow_concat: bool) -> Option<CalibrationResult> {
+    // For each operand index, whether every operator from there on can only grow the running value,
+    // in which case a running value already past the result can be dropped
+    fn prunable_from(&self, operators: &[Box<dyn Operator>]) -> Vec<bool> {
+        let mut ret = vec![true; self.operands.len() + 1];
+        for idx in (0..self.operands.len()).rev() {
+            ret[idx] = ret[idx + 1] && operators.iter().all(|operator| operator.never_decreases(self.operands[idx]));
+        }
+
+        ret
+    }
+
+    fn get_calibration_result<'a>(&self, operators: &'a [Box<dyn Operator>]) -> Option<CalibrationResult<'a>> {
         if self.operands.is_empty() { return None }
 
+        let prunable_from = self.prunable_from(operators);
         let start_value = self.operands[0];
         let mut dfs_stack: Vec<CalibrationResult> = vec![CalibrationResult{
             operands: vec![start_value],
@@ -49,88 +98,318 @@ impl CalibrationEquation {
             result: start_value,
         }];
 
-        while !dfs_stack.is_empty() {
-            let mut state = dfs_stack.pop().unwrap();
-            let next_operand = self.operands[state.operands.len()];
-            state.operands.push(next_operand);
-            let incomplete = state.operands.len() < self.operands.len();
+        while let Some(state) = dfs_stack.pop() {
+            let idx = state.operands.len();
+            if idx == self.operands.len() {
+                if state.result == self.result {
+                    return Some(state)
+                }
+                continue
+            }
+
+            let next_operand = self.operands[idx];
+            for operator in operators.iter() {
+                // Values that overflow a u64 are dropped. That's only exact when nothing later can
+                // bring the value back down (prunable_from), otherwise a solution that passes through
+                // such a value (e.g. on its way to being divided) isn't found.
+                let Some(result) = operator.apply(state.result, next_operand) else { continue };
+                if result > self.result && prunable_from[idx + 1] { continue } // Overshot the result
+
+                let mut next_state = state.clone();
+                next_state.operands.push(next_operand);
+                next_state.operators.push(operator.as_ref());
+                next_state.result = result;
+                dfs_stack.push(next_state);
+            }
+        }
 
-            // Set up two next possible states
-            let mut add_state = state;
-            let mut mul_state = add_state.clone();
-            let mut concat_state = add_state.clone();
+        None
+    }
 
-            // Modify the next possible states based on their operators
-            add_state.operators.push(Operation::Add);
-            add_state.result += next_operand;
+    // Every operator sequence that evaluates to the result, not just the first one found
+    fn get_all_calibration_results<'a>(&self, operators: &'a [Box<dyn Operator>]) -> Vec<CalibrationResult<'a>> {
+        if self.operands.is_empty() { return vec![] }
 
-            mul_state.operators.push(Operation::Multiply);
-            mul_state.result *= next_operand;
+        let mut ret: Vec<CalibrationResult> = vec![];
+        let mut chosen: Vec<&dyn Operator> = vec![];
+        self.collect_calibration_results(operators, &self.prunable_from(operators), self.operands[0], &mut chosen, &mut ret);
+        ret
+    }
 
-            if allow_concat {
-                concat_state.operators.push(Operation::Concat);
-                concat_state.result = CalibrationEquation::concatenate_operands(concat_state.result, next_operand);
+    fn collect_calibration_results<'a>(
+        &self,
+        operators: &'a [Box<dyn Operator>],
+        prunable_from: &[bool],
+        value: u64,
+        chosen: &mut Vec<&'a dyn Operator>,
+        ret: &mut Vec<CalibrationResult<'a>>,
+    ) {
+        let next_idx = chosen.len() + 1;
+        if next_idx == self.operands.len() {
+            if value == self.result {
+                ret.push(CalibrationResult{operands: self.operands.clone(), operators: chosen.clone(), result: value});
             }
+            return;
+        }
 
-            // Push if incomplete and not overshooting result
-            // Return if complete and exactly at result
-            if incomplete && add_state.result <= self.result {
-                dfs_stack.push(add_state);
-            } else if !incomplete && add_state.result == self.result {
-                return Some(add_state)
-            } // otherwise, drop this path
-
-            if incomplete && mul_state.result <= self.result {
-                dfs_stack.push(mul_state);
-            } else if !incomplete && mul_state.result == self.result {
-                return Some(mul_state)
-            } // otherwise, drop this path
-
-            if allow_concat {
-                if incomplete && concat_state.result <= self.result {
-                    dfs_stack.push(concat_state);
-                } else if !incomplete && concat_state.result == self.result {
-                    return Some(concat_state)
-                } // otherwise, drop this path
-            }
+        for operator in operators.iter() {
+            let Some(next_value) = operator.apply(value, self.operands[next_idx]) else { continue };
+            if next_value > self.result && prunable_from[next_idx + 1] { continue }
+
+            chosen.push(operator.as_ref());
+            self.collect_calibration_results(operators, prunable_from, next_value, chosen, ret);
+            chosen.pop();
         }
+    }
 
-        None
+    // Same as the number of results from get_all_calibration_results, but memoized on
+    // (operand index, running value) so shared sub-results are only counted once
+    fn count_calibration_results(&self, operators: &[Box<dyn Operator>]) -> u64 {
+        if self.operands.is_empty() { return 0 }
+
+        let mut memo: HashMap<(usize, u64), u64> = HashMap::new();
+        self.count_from(operators, &self.prunable_from(operators), 1, self.operands[0], &mut memo)
     }
 
-    fn concatenate_operands(a: u64, b: u64) -> u64 {
-        let mut result = a;
-        let mut b_reduce = b;
-        while b_reduce != 0 {
-            result *= 10;
-            b_reduce /= 10;
+    fn count_from(
+        &self,
+        operators: &[Box<dyn Operator>],
+        prunable_from: &[bool],
+        next_idx: usize,
+        value: u64,
+        memo: &mut HashMap<(usize, u64), u64>,
+    ) -> u64 {
+        if next_idx == self.operands.len() {
+            return if value == self.result { 1 } else { 0 };
         }
-        result + b
+        if let Some(&count) = memo.get(&(next_idx, value)) {
+            return count;
+        }
+
+        let count = operators.iter().map(|operator| {
+            match operator.apply(value, self.operands[next_idx]) {
+                Some(next_value) if next_value <= self.result || !prunable_from[next_idx + 1] => {
+                    self.count_from(operators, prunable_from, next_idx + 1, next_value, memo)
+                },
+                _ => 0,
+            }
+        }).sum();
+        memo.insert((next_idx, value), count);
+
+        count
     }
 }
 
-fn part1(equations: &Vec<CalibrationEquation>) {
-    let total_calibration_result: u64 = equations.iter().filter_map(|equation| {
-            equation.get_calibration_result(false).map(|res| res.result)
-    }).sum();
+fn parse_operators(names: &[&str]) -> Result<Vec<Box<dyn Operator>>> {
+    names.iter().map(|name| {
+        operator::from_name(name).ok_or_else(|| From::from(format!("Unknown operator: {}", name)))
+    }).collect()
+}
+
+// e.g. "+ and *" or "+, *, and ||"
+fn describe(operators: &[Box<dyn Operator>]) -> String {
+    let symbols: Vec<String> = operators.iter().map(|operator| operator.symbol()).collect();
+    match symbols.as_slice() {
+        [] => "no operators".to_owned(),
+        [only] => only.clone(),
+        [first, second] => format!("{} and {}", first, second),
+        [rest @ .., last] => format!("{}, and {}", rest.join(", "), last),
+    }
+}
+
+fn print_ambiguous(equations: &[CalibrationEquation], operators: &[Box<dyn Operator>], solver: &dyn Solver, list: bool) {
+    let mut ambiguous = 0usize;
+    for equation in equations {
+        let count = solver.count(equation, operators);
+        if count < 2 { continue }
+
+        ambiguous += 1;
+        if !list { continue }
+
+        println!("{} has {} solutions:", equation.result, count);
+        for result in solver.solutions(equation, operators) {
+            println!("    {} = {}", result, result.result);
+        }
+    }
 
-    println!("Total calibration result with + and *: {}", total_calibration_result);
+    println!("# of equations with more than one solution: {}", ambiguous);
 }
 
-fn part2(equations: &Vec<CalibrationEquation>) {
+fn solve(equations: &[CalibrationEquation], operators: &[Box<dyn Operator>], solver: &dyn Solver, list_ambiguous: bool) {
+    print_ambiguous(equations, operators, solver, list_ambiguous);
     let total_calibration_result: u64 = equations.iter().filter_map(|equation| {
-        equation.get_calibration_result(true).map(|res| res.result)
+        solver.first(equation, operators).map(|res| res.result)
     }).sum();
 
-    println!("Total calibration result with +, *, and ||: {}", total_calibration_result);
+    println!("Total calibration result with {}: {}", describe(operators), total_calibration_result);
+}
+
+fn bench(equations: &[CalibrationEquation], operators: &[Box<dyn Operator>]) -> Result<()> {
+    let mut solutions: Vec<Vec<Vec<String>>> = vec![];
+    for kind in [SolverKind::Forward, SolverKind::Backward] {
+        let solver = kind.solver();
+        let (mut solve_time, mut count_time) = (Duration::ZERO, Duration::ZERO);
+        let mut kind_solutions: Vec<Vec<String>> = vec![];
+        for equation in equations {
+            let start = Instant::now();
+            let mut found: Vec<String> = solver.solutions(equation, operators).iter().map(|res| res.to_string()).collect();
+            solve_time += start.elapsed();
+
+            let start = Instant::now();
+            let count = solver.count(equation, operators);
+            count_time += start.elapsed();
+
+            if count != found.len() as u64 {
+                return Err(From::from(format!(
+                    "{:?} solver counted {} solutions to {} but found {}", kind, count, equation.result, found.len()
+                )));
+            }
+            found.sort();
+            kind_solutions.push(found);
+        }
+
+        println!("{:?} solver with {}: all solutions in {:?}, counts in {:?}", kind, describe(operators), solve_time, count_time);
+        solutions.push(kind_solutions);
+    }
+
+    let disagreements: Vec<u64> = equations.iter().zip(solutions[0].iter().zip(solutions[1].iter()))
+        .filter(|(_, (forward, backward))| forward != backward)
+        .map(|(equation, _)| equation.result)
+        .collect();
+    if disagreements.is_empty() {
+        println!("Solvers agree on all {} equations", equations.len());
+    } else {
+        println!("Solvers disagree on {} equations: {:?}", disagreements.len(), disagreements);
+    }
+
+    Ok(())
+}
+
+fn part1(equations: &[CalibrationEquation], solver: &dyn Solver, list_ambiguous: bool) -> Result<()> {
+    solve(equations, &parse_operators(&["add", "mul"])?, solver, list_ambiguous);
+    Ok(())
+}
+
+fn part2(equations: &[CalibrationEquation], solver: &dyn Solver, list_ambiguous: bool) -> Result<()> {
+    solve(equations, &parse_operators(&["add", "mul", "concat"])?, solver, list_ambiguous);
+    Ok(())
 }
 
 fn main() -> Result<()> {
-    let file_path = util::file::get_input_file_path();
-    let equations = util::file::read_lines_to_type::<CalibrationEquation>(file_path)?;
+    let args = Args::parse();
+    let equations = util::file::read_lines_to_type::<CalibrationEquation>(args.file)?;
+
+    let operator_sets: Vec<Vec<Box<dyn Operator>>> = match args.operators {
+        Some(names) => {
+            let names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+            vec![parse_operators(&names)?]
+        },
+        None => vec![parse_operators(&["add", "mul"])?, parse_operators(&["add", "mul", "concat"])?],
+    };
+
+    if args.bench {
+        for operators in operator_sets.iter() {
+            bench(&equations, operators)?;
+        }
+        return Ok(());
+    }
+
+    let solver = args.solver.solver();
+    if operator_sets.len() == 1 {
+        solve(&equations, &operator_sets[0], solver.as_ref(), args.ambiguous);
+        return Ok(());
+    }
 
-    part1(&equations);
-    part2(&equations);
+    part1(&equations, solver.as_ref(), args.ambiguous)?;
+    part2(&equations, solver.as_ref(), args.ambiguous)?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "190: 10 19
+3267: 81 40 27
+83: 17 5
+156: 15 6
+7290: 6 8 6 15
+161011: 16 10 13
+192: 17 8 14
+21037: 9 7 18 13
+292: 11 6 16 20";
+
+    fn equations(text: &str) -> Vec<CalibrationEquation> {
+        text.lines().map(|line| line.parse::<CalibrationEquation>().unwrap()).collect()
+    }
+
+    fn total(equations: &[CalibrationEquation], operators: &[Box<dyn Operator>], solver: &dyn Solver) -> u64 {
+        equations.iter().filter_map(|equation| solver.first(equation, operators).map(|res| res.result)).sum()
+    }
+
+    fn sorted_solutions(equation: &CalibrationEquation, operators: &[Box<dyn Operator>], solver: &dyn Solver) -> Vec<String> {
+        let mut found: Vec<String> = solver.solutions(equation, operators).iter().map(|res| res.to_string()).collect();
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn example_totals() {
+        let equations = equations(EXAMPLE);
+        for kind in [SolverKind::Forward, SolverKind::Backward] {
+            let solver = kind.solver();
+            assert_eq!(total(&equations, &parse_operators(&["add", "mul"]).unwrap(), solver.as_ref()), 3749);
+            assert_eq!(total(&equations, &parse_operators(&["add", "mul", "concat"]).unwrap(), solver.as_ref()), 11387);
+        }
+    }
+
+    #[test]
+    fn every_assignment_is_listed() {
+        let operators = parse_operators(&["add", "mul"]).unwrap();
+        let equations = equations("3267: 81 40 27\n4: 2 2\n292: 11 6 16 20\n83: 17 5");
+        for kind in [SolverKind::Forward, SolverKind::Backward] {
+            let solver = kind.solver();
+            assert_eq!(sorted_solutions(&equations[0], &operators, solver.as_ref()), vec!["81 * 40 + 27", "81 + 40 * 27"]);
+            assert_eq!(sorted_solutions(&equations[1], &operators, solver.as_ref()), vec!["2 * 2", "2 + 2"]);
+            assert_eq!(sorted_solutions(&equations[2], &operators, solver.as_ref()), vec!["11 + 6 * 16 + 20"]);
+            assert!(sorted_solutions(&equations[3], &operators, solver.as_ref()).is_empty());
+            assert_eq!(solver.count(&equations[1], &operators), 2);
+        }
+    }
+
+    #[test]
+    fn solvers_agree_with_division() {
+        let equations = equations("12615: 804 5 2 1 5 7 7 7 1 3 3 5
+7: 100 9 2
+3: 17 5 1 1
+0: 5 9 3
+1046107888: 263 576 34 8 863");
+        let operators = parse_operators(&["add", "mul", "div"]).unwrap();
+        let (forward, backward) = (SolverKind::Forward.solver(), SolverKind::Backward.solver());
+        for equation in equations.iter() {
+            let solutions = sorted_solutions(equation, &operators, forward.as_ref());
+            assert_eq!(sorted_solutions(equation, &operators, backward.as_ref()), solutions);
+            assert_eq!(backward.count(equation, &operators), solutions.len() as u64);
+            assert_eq!(forward.count(equation, &operators), solutions.len() as u64);
+            assert_eq!(backward.first(equation, &operators).is_some(), !solutions.is_empty());
+        }
+        assert_eq!(backward.count(&equations[0], &operators), 4);
+    }
+
+    #[test]
+    fn solvers_agree_with_every_operator() {
+        // Roots past 2^53 that a floating-point estimate gets wrong
+        let equations = equations(&format!("{}\n4611686018427388404: 4611686018427388404 1\n18446744030759878681: 4294967291 2", EXAMPLE));
+        let operators = parse_operators(&["add", "sub", "mul", "div", "xor", "pow", "concat2"]).unwrap();
+        let (forward, backward) = (SolverKind::Forward.solver(), SolverKind::Backward.solver());
+        for equation in equations.iter() {
+            assert_eq!(sorted_solutions(equation, &operators, backward.as_ref()), sorted_solutions(equation, &operators, forward.as_ref()));
+        }
+    }
+
+    #[test]
+    fn bench_reports_instead_of_panicking() {
+        assert!(bench(&equations(EXAMPLE), &parse_operators(&["add", "mul", "div"]).unwrap()).is_ok());
+    }
+}
@@ -0,0 +1,241 @@
+// A left-to-right binary operator for calibration equations. All arithmetic is checked, and an
+// operator returns None instead of wrapping or panicking when the result doesn't fit in a u64.
+pub trait Operator {
+    fn symbol(&self) -> String;
+
+    fn apply(&self, a: u64, b: u64) -> Option<u64>;
+
+    // Whether apply(a, operand) >= a for every a, which is what makes it safe to abandon a
+    // running value once it overshoots the result
+    fn never_decreases(&self, operand: u64) -> bool;
+
+    // Every `a` for which apply(a, operand) == Some(result), or None if there are too many to list
+    // (e.g. anything times 0 is 0)
+    fn invert(&self, result: u64, operand: u64) -> Option<Vec<u64>>;
+}
+
+// Beyond this many preimages, inverting gives up and lets the solver search forwards instead, since
+// every level of division could otherwise multiply the backward search by up to this much
+const MAX_PREIMAGES: u64 = 16;
+
+pub struct Add;
+pub struct Subtract;
+pub struct Multiply;
+pub struct Divide;
+pub struct Xor;
+pub struct Power;
+pub struct Concat {
+    pub base: u64,
+}
+
+impl Operator for Add {
+    fn symbol(&self) -> String { "+".to_owned() }
+    fn apply(&self, a: u64, b: u64) -> Option<u64> { a.checked_add(b) }
+    fn never_decreases(&self, _: u64) -> bool { true }
+
+    // Only possible once the result is at least as big as the operand
+    fn invert(&self, result: u64, operand: u64) -> Option<Vec<u64>> {
+        Some(result.checked_sub(operand).into_iter().collect())
+    }
+}
+
+impl Operator for Subtract {
+    fn symbol(&self) -> String { "-".to_owned() }
+    fn apply(&self, a: u64, b: u64) -> Option<u64> { a.checked_sub(b) }
+    fn never_decreases(&self, operand: u64) -> bool { operand == 0 }
+
+    fn invert(&self, result: u64, operand: u64) -> Option<Vec<u64>> {
+        Some(result.checked_add(operand).into_iter().collect())
+    }
+}
+
+impl Operator for Multiply {
+    fn symbol(&self) -> String { "*".to_owned() }
+    fn apply(&self, a: u64, b: u64) -> Option<u64> { a.checked_mul(b) }
+    fn never_decreases(&self, operand: u64) -> bool { operand >= 1 }
+
+    // Only possible when the operand divides the result evenly
+    fn invert(&self, result: u64, operand: u64) -> Option<Vec<u64>> {
+        match operand {
+            0 if result == 0 => None,
+            0 => Some(vec![]),
+            _ if result.is_multiple_of(operand) => Some(vec![result / operand]),
+            _ => Some(vec![]),
+        }
+    }
+}
+
+// Integer division, rounding down
+impl Operator for Divide {
+    fn symbol(&self) -> String { "/".to_owned() }
+    fn apply(&self, a: u64, b: u64) -> Option<u64> { a.checked_div(b) }
+    fn never_decreases(&self, operand: u64) -> bool { operand == 1 }
+
+    // Rounding down means every `a` from result * operand up to the next multiple works
+    fn invert(&self, result: u64, operand: u64) -> Option<Vec<u64>> {
+        if operand > MAX_PREIMAGES {
+            return None;
+        }
+
+        let Some(low) = result.checked_mul(operand) else { return Some(vec![]) };
+        Some((low..=low.saturating_add(operand.saturating_sub(1))).take(operand as usize).collect())
+    }
+}
+
+impl Operator for Xor {
+    fn symbol(&self) -> String { "^".to_owned() }
+    fn apply(&self, a: u64, b: u64) -> Option<u64> { Some(a ^ b) }
+    fn never_decreases(&self, operand: u64) -> bool { operand == 0 }
+    fn invert(&self, result: u64, operand: u64) -> Option<Vec<u64>> { Some(vec![result ^ operand]) }
+}
+
+impl Operator for Power {
+    fn symbol(&self) -> String { "**".to_owned() }
+
+    fn apply(&self, a: u64, b: u64) -> Option<u64> {
+        match (a, b) {
+            (_, 0) => Some(1),
+            (0 | 1, _) => Some(a),
+            _ => u32::try_from(b).ok().and_then(|exp| a.checked_pow(exp)),
+        }
+    }
+
+    fn never_decreases(&self, operand: u64) -> bool { operand >= 1 }
+
+    // Exact integer root, by binary search: a floating-point root can be off by more than one for
+    // results past 2^53
+    fn invert(&self, result: u64, operand: u64) -> Option<Vec<u64>> {
+        match (result, operand) {
+            (1, 0) => None,
+            (_, 0) => Some(vec![]),
+            (_, 1) | (0 | 1, _) => Some(vec![result]),
+            _ => {
+                // Powers past u64::MAX count as too big
+                let (mut low, mut high) = (2u64, result);
+                while low < high {
+                    let mid = low + (high - low) / 2;
+                    if self.apply(mid, operand).is_none_or(|power| power >= result) {
+                        high = mid;
+                    } else {
+                        low = mid + 1;
+                    }
+                }
+                Some(Some(low).filter(|&a| self.apply(a, operand) == Some(result)).into_iter().collect())
+            },
+        }
+    }
+}
+
+impl Concat {
+    // Smallest power of the base with more digits than `b`. As with the original `||`, `0` has no
+    // digits, so appending it leaves the value as it is.
+    pub fn shift(&self, b: u64) -> Option<u64> {
+        if b == 0 {
+            return Some(1);
+        }
+
+        let mut shift = self.base;
+        while shift <= b {
+            shift = shift.checked_mul(self.base)?;
+        }
+
+        Some(shift)
+    }
+}
+
+// Appends the digits of `b` to `a`, both written in the given base
+impl Operator for Concat {
+    fn symbol(&self) -> String {
+        if self.base == 10 { "||".to_owned() } else { format!("||{}", self.base) }
+    }
+
+    fn apply(&self, a: u64, b: u64) -> Option<u64> {
+        a.checked_mul(self.shift(b)?)?.checked_add(b)
+    }
+
+    fn never_decreases(&self, _: u64) -> bool { true }
+
+    // Only possible when the result ends in the operand's digits, which get stripped off
+    fn invert(&self, result: u64, operand: u64) -> Option<Vec<u64>> {
+        let Some(shift) = self.shift(operand) else { return Some(vec![]) };
+        match result.checked_sub(operand) {
+            Some(rest) if rest % shift == 0 => Some(vec![rest / shift]),
+            _ => Some(vec![]),
+        }
+    }
+}
+
+// Operators by name, for picking them from the command line: `add`, `sub`, `mul`, `div`, `xor`,
+// `pow`, `concat` (base 10) or `concat<base>` for bases 2 to 36 (e.g. `concat2`)
+pub fn from_name(name: &str) -> Option<Box<dyn Operator>> {
+    match name {
+        "add" => Some(Box::new(Add)),
+        "sub" => Some(Box::new(Subtract)),
+        "mul" => Some(Box::new(Multiply)),
+        "div" => Some(Box::new(Divide)),
+        "xor" => Some(Box::new(Xor)),
+        "pow" => Some(Box::new(Power)),
+        "concat" => Some(Box::new(Concat{base: 10})),
+        _ => {
+            let base = name.strip_prefix("concat")?.parse::<u64>().ok()?;
+            if (2..=36).contains(&base) { Some(Box::new(Concat{base})) } else { None }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn divide_inverts_to_every_value_rounding_down_to_the_result() {
+        assert_eq!(Divide.invert(3, 5), Some(vec![15, 16, 17, 18, 19]));
+        assert_eq!(Divide.invert(3, 1), Some(vec![3]));
+        assert_eq!(Divide.invert(0, 4), Some(vec![0, 1, 2, 3]));
+        assert!(Divide.invert(3, 5).unwrap().iter().all(|&a| Divide.apply(a, 5) == Some(3)));
+    }
+
+    #[test]
+    fn divide_gives_up_on_too_many_preimages() {
+        assert_eq!(Divide.invert(3, MAX_PREIMAGES).map(|preimages| preimages.len()), Some(MAX_PREIMAGES as usize));
+        assert_eq!(Divide.invert(3, MAX_PREIMAGES + 1), None);
+        assert_eq!(Divide.invert(u64::MAX, 2), Some(vec![]));
+    }
+
+    #[test]
+    fn power_inverts_to_exact_roots() {
+        assert_eq!(Power.invert(4611686018427388404, 1), Some(vec![4611686018427388404]));
+        assert_eq!(Power.invert(18446744030759878681, 2), Some(vec![4294967291]));
+        assert_eq!(Power.invert(18446744030759878680, 2), Some(vec![]));
+        assert_eq!(Power.invert(1 << 63, 63), Some(vec![2]));
+        assert_eq!(Power.invert(u64::MAX, 64), Some(vec![]));
+        assert_eq!(Power.invert(243, 5), Some(vec![3]));
+        assert_eq!(Power.invert(1, 7), Some(vec![1]));
+        assert_eq!(Power.invert(1, 0), None);
+        assert_eq!(Power.invert(5, 0), Some(vec![]));
+    }
+
+    #[test]
+    fn concat_appends_digits() {
+        let concat = Concat{base: 10};
+        assert_eq!(concat.apply(12, 345), Some(12345));
+        assert_eq!(concat.apply(12, 10), Some(1210));
+        assert_eq!(concat.apply(12, 0), Some(12));
+        assert_eq!(Concat{base: 2}.apply(0b10, 0b11), Some(0b1011));
+        assert_eq!(concat.apply(u64::MAX / 10, 99), None);
+    }
+
+    #[test]
+    fn concat_inverts_by_stripping_digits() {
+        let concat = Concat{base: 10};
+        assert_eq!(concat.invert(12345, 345), Some(vec![12]));
+        assert_eq!(concat.invert(12345, 45), Some(vec![123]));
+        assert_eq!(concat.invert(12345, 44), Some(vec![]));
+        assert_eq!(concat.invert(12, 0), Some(vec![12]));
+    }
+
+    #[test]
+    fn divide_by_zero_has_no_result() {
+        assert_eq!(Divide.apply(7, 0), None);
+    }
+}
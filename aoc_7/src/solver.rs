@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use clap::ValueEnum;
+use super::{CalibrationEquation, CalibrationResult};
+use super::operator::Operator;
+
+pub trait Solver {
+    fn solutions<'a>(&self, equation: &CalibrationEquation, operators: &'a [Box<dyn Operator>]) -> Vec<CalibrationResult<'a>>;
+
+    fn count(&self, equation: &CalibrationEquation, operators: &[Box<dyn Operator>]) -> u64;
+
+    fn first<'a>(&self, equation: &CalibrationEquation, operators: &'a [Box<dyn Operator>]) -> Option<CalibrationResult<'a>> {
+        self.solutions(equation, operators).into_iter().next()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SolverKind {
+    Forward,
+    Backward,
+}
+
+impl SolverKind {
+    pub fn solver(&self) -> Box<dyn Solver> {
+        match self {
+            SolverKind::Forward => Box::new(Forward),
+            SolverKind::Backward => Box::new(Backward),
+        }
+    }
+}
+
+// Left-to-right DFS over running values, pruning ones that overshoot the result
+pub struct Forward;
+
+impl Solver for Forward {
+    fn solutions<'a>(&self, equation: &CalibrationEquation, operators: &'a [Box<dyn Operator>]) -> Vec<CalibrationResult<'a>> {
+        equation.get_all_calibration_results(operators)
+    }
+
+    fn count(&self, equation: &CalibrationEquation, operators: &[Box<dyn Operator>]) -> u64 {
+        equation.count_calibration_results(operators)
+    }
+
+    fn first<'a>(&self, equation: &CalibrationEquation, operators: &'a [Box<dyn Operator>]) -> Option<CalibrationResult<'a>> {
+        equation.get_calibration_result(operators)
+    }
+}
+
+// Right-to-left DFS, un-applying each operator from the result. Most operators can only be undone
+// in one way or not at all (e.g. a product only when the operand divides it evenly), which prunes
+// far harder than overshooting does. Where an operator can't be undone into fewer values than there
+// are operators to try forwards (e.g. dividing by 7 has 7 preimages), the operands to the left are
+// evaluated forwards instead, and from there on only values those can reach are followed.
+pub struct Backward;
+
+struct BackwardSearch<'e, 'a> {
+    equation: &'e CalibrationEquation,
+    operators: &'a [Box<dyn Operator>],
+    prunable_from: Vec<bool>,
+    reachable: Vec<HashMap<u64, u64>>, // Value -> ways to get it from operands 0 to idx, built lazily
+    memo: HashMap<(usize, u64), u64>,
+}
+
+impl<'e, 'a> BackwardSearch<'e, 'a> {
+    fn new(equation: &'e CalibrationEquation, operators: &'a [Box<dyn Operator>]) -> BackwardSearch<'e, 'a> {
+        BackwardSearch{
+            equation,
+            operators,
+            prunable_from: equation.prunable_from(operators),
+            reachable: vec![],
+            memo: HashMap::new(),
+        }
+    }
+
+    // Evaluates forwards up to operand `idx`, dropping values that overshoot the result for good
+    fn reach(&mut self, idx: usize) {
+        if self.reachable.is_empty() {
+            self.reachable.push(HashMap::from([(self.equation.operands[0], 1)]));
+        }
+
+        while self.reachable.len() <= idx {
+            let next_idx = self.reachable.len();
+            let operand = self.equation.operands[next_idx];
+            let mut next: HashMap<u64, u64> = HashMap::new();
+            for (&value, &ways) in self.reachable[next_idx - 1].iter() {
+                for operator in self.operators.iter() {
+                    let Some(next_value) = operator.apply(value, operand) else { continue };
+                    if next_value > self.equation.result && self.prunable_from[next_idx + 1] { continue }
+
+                    *next.entry(next_value).or_default() += ways;
+                }
+            }
+            self.reachable.push(next);
+        }
+    }
+
+    // The values before operand `idx` that the operator takes to `target`
+    fn preimages(&mut self, idx: usize, target: u64, operator: &dyn Operator) -> Vec<u64> {
+        let operand = self.equation.operands[idx];
+        match operator.invert(target, operand) {
+            Some(preimages) if preimages.len() <= self.operators.len() && self.reachable.len() < idx => preimages,
+            Some(preimages) => {
+                self.reach(idx - 1);
+                preimages.into_iter().filter(|preimage| self.reachable[idx - 1].contains_key(preimage)).collect()
+            },
+            None => {
+                self.reach(idx - 1);
+                self.reachable[idx - 1].keys().copied().filter(|&value| operator.apply(value, operand) == Some(target)).collect()
+            },
+        }
+    }
+
+    fn collect(
+        &mut self,
+        idx: usize,
+        target: u64,
+        chosen: &mut Vec<&'a dyn Operator>, // Operators after operand `idx`, right to left
+        limit: usize, // Stop once this many solutions are found
+        ret: &mut Vec<CalibrationResult<'a>>,
+    ) {
+        if ret.len() >= limit {
+            return;
+        }
+        if idx == 0 {
+            if target == self.equation.operands[0] {
+                ret.push(CalibrationResult{
+                    operands: self.equation.operands.clone(),
+                    operators: chosen.iter().rev().copied().collect(),
+                    result: self.equation.result,
+                });
+            }
+            return;
+        }
+
+        let operators = self.operators;
+        for operator in operators.iter() {
+            chosen.push(operator.as_ref());
+            for preimage in self.preimages(idx, target, operator.as_ref()) {
+                // Counting is memoized, so this keeps the search to branches that lead somewhere
+                if self.count_from(idx - 1, preimage) > 0 {
+                    self.collect(idx - 1, preimage, chosen, limit, ret);
+                }
+            }
+            chosen.pop();
+        }
+    }
+
+    fn count_from(&mut self, idx: usize, target: u64) -> u64 {
+        if let Some(reachable) = self.reachable.get(idx) {
+            return reachable.get(&target).copied().unwrap_or(0);
+        }
+        if idx == 0 {
+            return if target == self.equation.operands[0] { 1 } else { 0 };
+        }
+        if let Some(&count) = self.memo.get(&(idx, target)) {
+            return count;
+        }
+
+        let operators = self.operators;
+        let count = operators.iter().map(|operator| {
+            self.preimages(idx, target, operator.as_ref()).into_iter().map(|preimage| self.count_from(idx - 1, preimage)).sum::<u64>()
+        }).sum();
+        self.memo.insert((idx, target), count);
+
+        count
+    }
+}
+
+impl Solver for Backward {
+    fn solutions<'a>(&self, equation: &CalibrationEquation, operators: &'a [Box<dyn Operator>]) -> Vec<CalibrationResult<'a>> {
+        if equation.operands.is_empty() { return vec![] }
+
+        let mut ret: Vec<CalibrationResult> = vec![];
+        BackwardSearch::new(equation, operators).collect(equation.operands.len() - 1, equation.result, &mut vec![], usize::MAX, &mut ret);
+        ret
+    }
+
+    fn count(&self, equation: &CalibrationEquation, operators: &[Box<dyn Operator>]) -> u64 {
+        if equation.operands.is_empty() { return 0 }
+
+        BackwardSearch::new(equation, operators).count_from(equation.operands.len() - 1, equation.result)
+    }
+
+    fn first<'a>(&self, equation: &CalibrationEquation, operators: &'a [Box<dyn Operator>]) -> Option<CalibrationResult<'a>> {
+        if equation.operands.is_empty() { return None }
+
+        let mut ret: Vec<CalibrationResult> = vec![];
+        BackwardSearch::new(equation, operators).collect(equation.operands.len() - 1, equation.result, &mut vec![], 1, &mut ret);
+        ret.into_iter().next()
+    }
+}
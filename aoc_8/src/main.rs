@@ -1,78 +1,156 @@
 use util::res::Result;
+use util::file::GenericParseError;
 use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use clap::{Parser, ValueEnum};
+use report::AntinodeReport;
+
+mod report;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ReportFormat {
+    Table,
+    Json,
+}
+
+#[derive(Parser, Debug)]
+struct Args {
+    #[arg(short, long)]
+    file: PathBuf,
+
+    // Find antinodes at these ratios along each antenna pair instead of the two puzzle parts
+    // (e.g. `2,1/3,2/3`)
+    #[arg(short, long, value_delimiter = ',', conflicts_with = "harmonics")]
+    ratios: Option<Vec<Ratio>>,
+
+    // Find every k-th whole-coordinate point along each antenna pair's line instead of the two puzzle parts
+    #[arg(long)]
+    harmonics: Option<i64>,
+
+    // Draw each frequency's antennas and antinodes
+    #[arg(long)]
+    render: bool,
+
+    // Break antinodes down by frequency and by the antenna pairs that produced them
+    #[arg(long, value_enum)]
+    report: Option<ReportFormat>,
+}
+
+// Antinodes of each frequency, each with every pair of antennas (by index into that frequency's
+// antennas, lowest first) that produces it
+type Antinodes = HashMap<(char, Point), Vec<(usize, usize)>>;
+
+// Coordinates in any number of dimensions: (row, column) for grid input, (x, y, z, ...) for coordinate input
+type Point = Vec<i64>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Ratio {
+    num: i64,
+    den: i64,
+}
+
+impl std::str::FromStr for Ratio {
+    type Err = GenericParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (num, den) = s.trim().split_once('/').unwrap_or((s.trim(), "1"));
+        let ratio = Ratio{num: num.parse::<i64>()?, den: den.parse::<i64>()?};
+        if ratio.den == 0 {
+            return Err(GenericParseError::ValueError(format!("Invalid ratio: {}", s)));
+        }
+
+        Ok(ratio)
+    }
+}
+
+enum AntinodeRule {
+    // Points `other + ratio * (self - other)` for each ratio, wherever that's a whole coordinate.
+    // Since every pair is visited in both orders, `2` covers the puzzle's "twice as far from one
+    // antenna as the other" antinodes on both sides.
+    Ratios(Vec<Ratio>),
+
+    // Every `every`-th whole-coordinate point on the line through both antennas, counting from the
+    // antennas themselves (the puzzle's resonant harmonics are `every = 1`)
+    Harmonics { every: i64 },
+}
 
 struct Antenna {
     frequency: char,
-    location: (usize, usize),
+    location: Point,
 }
 
 struct AntennaNetwork {
-    width: usize,
-    height: usize,
+    bounds: Point, // Exclusive upper bound in each dimension
     antennas: HashMap<char, Vec<Antenna>>, 
 }
 
 // Returns gcd, x, y such that ax + by = gcd
-pub fn extended_gcd(a: i32, b: i32) -> (i32, i32, i32) {
+pub fn extended_gcd(a: i64, b: i64) -> (i64, i64, i64) {
 	if a == 0 { return (b, 0, 1); }
 	let (gcd, x, y) = extended_gcd(b % a, a);
     (gcd, (y - (b/a) * x), x)
 }
 
+fn in_bounds(point: &[i64], bounds: &[i64]) -> bool {
+    point.iter().zip(bounds.iter()).all(|(&coord, &bound)| (0..bound).contains(&coord))
+}
+
 impl Antenna {
-    fn get_antinodes(&self, other: &Antenna, y_limit: usize, x_limit: usize, allow_resonant_harmonics: bool) -> Vec<(usize, usize)> {
+    fn get_antinodes(&self, other: &Antenna, bounds: &[i64], rule: &AntinodeRule) -> Vec<Point> {
         // Ideally we won't be comparing antennas of different frequencies in the
         // first place, but this safety mechanism doesn't hurt to have
         if self.frequency != other.frequency { return vec![]; }
 
-        let mut antinodes_signed: Vec<(i32, i32)> = vec![];
-        let self_signed = (self.location.0 as i32, self.location.1 as i32);
-        let other_signed = (other.location.0 as i32, other.location.1 as i32);
-
-        // Prepare bounds ranges
-        let y_range = 0..(y_limit as i32);
-        let x_range = 0..(x_limit as i32);
-
-        // There are always 2 antinodes on either side of the pair of antennas
-        let diff = (self_signed.0 - other_signed.0, self_signed.1 - other_signed.1);
-        if allow_resonant_harmonics {
-            let diff_gcd = extended_gcd(diff.0.abs(), diff.1.abs()).0;
-            let reduced_diff = (diff.0 / diff_gcd, diff.1 / diff_gcd);
-
-            // Collect antinodes in both directions, including in between
-            // First loop is from `other` towards `self`, starting 1-step in
-            // Second loop is from `other` away from `self` (including `other`)
-            let mut position = (other_signed.0 + reduced_diff.0, other_signed.1 + reduced_diff.1);
-            while y_range.contains(&position.0) && x_range.contains(&position.1) {
-                antinodes_signed.push(position);
-                position = (position.0 + reduced_diff.0, position.1 + reduced_diff.1);
-            }
+        let mut antinodes: Vec<Point> = vec![];
+        let diff: Point = self.location.iter().zip(other.location.iter()).map(|(a, b)| a - b).collect();
+        match rule {
+            AntinodeRule::Ratios(ratios) => {
+                for ratio in ratios {
+                    // Only points with whole coordinates count
+                    if diff.iter().any(|d| (d * ratio.num) % ratio.den != 0) { continue }
 
-            position = other_signed;
-            while y_range.contains(&position.0) && x_range.contains(&position.1) {
-                antinodes_signed.push(position);
-                position = (position.0 - reduced_diff.0, position.1 - reduced_diff.1);
-            }
-        } else {
-            antinodes_signed.push((self_signed.0 + diff.0, self_signed.1 + diff.1));
-            antinodes_signed.push((other_signed.0 - diff.0, other_signed.1 - diff.1));
+                    antinodes.push(other.location.iter().zip(diff.iter()).map(|(b, d)| b + d * ratio.num / ratio.den).collect());
+                }
+            },
+            AntinodeRule::Harmonics { every } => {
+                let diff_gcd = diff.iter().fold(0, |acc, d| extended_gcd(acc, d.abs()).0);
+                if diff_gcd == 0 { return vec![]; } // Two antennas in the same place don't make a line
+                let step: Point = diff.iter().map(|d| d / diff_gcd * every).collect();
+
+                // Collect antinodes in both directions from `other`, including in between
+                for sign in [1, -1] {
+                    let mut position = other.location.clone();
+                    if sign == 1 {
+                        position = position.iter().zip(step.iter()).map(|(p, s)| p + s).collect();
+                    }
+
+                    while in_bounds(&position, bounds) {
+                        let next = position.iter().zip(step.iter()).map(|(p, s)| p + sign * s).collect();
+                        antinodes.push(position);
+                        position = next;
+                    }
+                }
+            },
         }
 
-        antinodes_signed.into_iter().filter_map(|antinode| {
-            if y_range.contains(&antinode.0) && x_range.contains(&antinode.1) {
-                Some((antinode.0 as usize, antinode.1 as usize))
-            } else {
-                None // discard antinodes with negative coordinates or out of bounds
-            }
-        }).collect()
+        // discard antinodes with negative coordinates or out of bounds
+        antinodes.retain(|antinode| in_bounds(antinode, bounds));
+        antinodes
     }
 }
 
 impl AntennaNetwork {
-    fn from_lines(lines: &Vec<String>) -> AntennaNetwork {
+    // Either a grid of characters (`.` for empty), or one antenna per line as `<frequency> (x,y,...)`
+    // in any number of dimensions, with an optional `bounds (x,y,...)` line (otherwise the bounds
+    // are just big enough to fit every antenna)
+    fn from_lines(lines: &[String]) -> Result<AntennaNetwork> {
+        let is_coordinate_format = lines.iter().find(|line| !line.trim().is_empty()).is_some_and(|line| line.contains('('));
+        if is_coordinate_format {
+            return AntennaNetwork::from_coordinate_lines(lines);
+        }
+
         let mut network = AntennaNetwork{
-            height: lines.len(),
-            width: lines[0].len(),
+            bounds: vec![lines.len() as i64, lines[0].len() as i64],
             antennas: HashMap::new(),
         };
 
@@ -80,59 +158,294 @@ impl AntennaNetwork {
             for (col, c) in line.chars().enumerate() {
                 if c == '.' { continue }
 
-                network.antennas.entry(c).or_insert(vec![]).push(Antenna{
+                network.antennas.entry(c).or_default().push(Antenna{
                     frequency: c,
-                    location: (row, col)
+                    location: vec![row as i64, col as i64],
                 });
             }
         }
 
-        network
+        Ok(network)
+    }
+
+    fn from_coordinate_lines(lines: &[String]) -> Result<AntennaNetwork> {
+        let parse_point = |raw: &str| -> std::result::Result<Point, GenericParseError> {
+            let inner = raw.trim().strip_prefix('(').and_then(|raw| raw.strip_suffix(')'))
+                .ok_or_else(|| GenericParseError::ValueError(format!("Invalid coordinates: {}", raw)))?;
+            inner.split(',').map(|coord| coord.trim().parse::<i64>().map_err(|e| e.into())).collect()
+        };
+
+        let mut bounds: Option<Point> = None;
+        let mut antennas: HashMap<char, Vec<Antenna>> = HashMap::new();
+        for line in lines.iter().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+            let (label, raw_point) = line.split_once(' ')
+                .ok_or_else(|| GenericParseError::ValueError(format!("Invalid antenna: {}", line)))?;
+            let point = parse_point(raw_point)?;
+
+            if label == "bounds" {
+                bounds = Some(point);
+                continue;
+            }
+
+            let mut label_chars = label.chars();
+            let (Some(frequency), None) = (label_chars.next(), label_chars.next()) else {
+                return Err(From::from(format!("Invalid frequency: {}", label)));
+            };
+            antennas.entry(frequency).or_default().push(Antenna{frequency, location: point});
+        }
+
+        let dimensions = antennas.values().flatten().map(|antenna| antenna.location.len()).max().unwrap_or(0);
+        if antennas.values().flatten().any(|antenna| antenna.location.len() != dimensions) {
+            return Err(From::from("Antennas have different numbers of dimensions".to_owned()));
+        }
+
+        let bounds = bounds.unwrap_or_else(|| (0..dimensions).map(|dim| {
+            antennas.values().flatten().map(|antenna| antenna.location[dim] + 1).max().unwrap_or(0)
+        }).collect());
+        if bounds.len() != dimensions {
+            return Err(From::from("Bounds and antennas have different numbers of dimensions".to_owned()));
+        }
+
+        Ok(AntennaNetwork{bounds, antennas})
     }
 
-    fn get_antinodes_in_bounds(&self, allow_resonant_harmonics: bool) -> HashSet<(char, (usize, usize))> {
-        let mut antinodes_in_bounds: HashSet<(char, (usize, usize))> = HashSet::new();
+    fn get_antinodes_in_bounds(&self, rule: &AntinodeRule) -> Antinodes {
+        let mut antinodes_in_bounds: Antinodes = HashMap::new();
         for (frequency, antennas) in &self.antennas {
             // Have to go over all pairs of antennas with the same frequency
             for (i, first_antenna) in antennas.iter().enumerate() {
                 for (j, second_antenna) in antennas.iter().enumerate() {
                     if i == j { continue }
-                    antinodes_in_bounds.extend(
-                        first_antenna.get_antinodes(second_antenna, self.height, self.width, allow_resonant_harmonics)
-                            .into_iter().map(|antinode| (*frequency, antinode))
-                            .collect::<Vec<(char, (usize, usize))>>()
-                    );
+                    let pair = (i.min(j), i.max(j));
+                    for antinode in first_antenna.get_antinodes(second_antenna, &self.bounds, rule) {
+                        let pairs = antinodes_in_bounds.entry((*frequency, antinode)).or_default();
+                        if !pairs.contains(&pair) {
+                            pairs.push(pair);
+                        }
+                    }
                 }
             }
         }
         
         antinodes_in_bounds
     }
+
+    // One grid per frequency: antennas by their frequency, antinodes as `#`, and `*` for both.
+    // The first two dimensions are rows and columns, with a separate grid per slice of any more.
+    fn render(&self, antinodes: &Antinodes) -> String {
+        if self.bounds.len() < 2 {
+            return "Can't draw fewer than 2 dimensions".to_owned();
+        }
+
+        let mut frequencies: Vec<&char> = self.antennas.keys().collect();
+        frequencies.sort();
+
+        let slices: usize = self.bounds[2..].iter().map(|&bound| bound.max(0) as usize).product();
+        let mut sections: Vec<String> = vec![];
+        for &frequency in frequencies {
+            let antenna_locations: HashSet<&Point> = self.antennas[&frequency].iter().map(|antenna| &antenna.location).collect();
+            for slice in 0..slices {
+                // Coordinates beyond the first two, for this slice
+                let mut rest: Point = vec![];
+                let mut remaining = slice as i64;
+                for &bound in self.bounds[2..].iter() {
+                    rest.push(remaining % bound);
+                    remaining /= bound;
+                }
+
+                let rows = (0..self.bounds[0]).map(|row| (0..self.bounds[1]).map(|col| {
+                    let mut point = vec![row, col];
+                    point.extend(rest.iter());
+                    match (antenna_locations.contains(&point), antinodes.contains_key(&(frequency, point))) {
+                        (true, true) => '*',
+                        (true, false) => frequency,
+                        (false, true) => '#',
+                        (false, false) => '.',
+                    }
+                }).collect::<String>()).collect::<Vec<String>>();
+
+                let header = if rest.is_empty() {
+                    format!("Frequency {}:", frequency)
+                } else {
+                    let rest = rest.iter().map(|coord| coord.to_string()).collect::<Vec<String>>();
+                    format!("Frequency {} at (_, _, {}):", frequency, rest.join(", "))
+                };
+                sections.push(format!("{}\n{}", header, rows.join("\n")));
+            }
+        }
+
+        sections.join("\n\n")
+    }
+}
+
+// What else to print about a rule's antinodes on the way to counting them
+#[derive(Clone, Copy, Default)]
+struct Show {
+    render: bool,
+    table: bool,
+}
+
+fn count_unique_locations(network: &AntennaNetwork, name: &str, rule: &AntinodeRule, show: Show) -> usize {
+    let antinodes_in_bounds = network.get_antinodes_in_bounds(rule);
+    if show.render {
+        println!("{}\n", network.render(&antinodes_in_bounds));
+    }
+    if show.table {
+        println!("{}\n", AntinodeReport::new(name, network, &antinodes_in_bounds).to_table());
+    }
+
+    let unique_locations = antinodes_in_bounds.into_keys().map(|(_, loc)| loc)
+        .collect::<HashSet<Point>>();
+    unique_locations.len()
 }
 
-fn part1(network: &AntennaNetwork) {
-    let antinodes_in_bounds = network.get_antinodes_in_bounds(false);
-    let unique_locations = antinodes_in_bounds.iter().map(|(_, loc)| *loc)
-        .collect::<HashSet<(usize, usize)>>();
+// Every rule's report in one array, without any of the counts
+fn json_report(network: &AntennaNetwork, rules: &[(&str, AntinodeRule)]) -> String {
+    let reports = rules.iter()
+        .map(|(name, rule)| AntinodeReport::new(name, network, &network.get_antinodes_in_bounds(rule)).to_json())
+        .collect::<Vec<String>>();
+    format!("[{}]", reports.join(","))
+}
+
+fn part1(network: &AntennaNetwork, rule: &AntinodeRule, show: Show) {
+    let unique_locations = count_unique_locations(network, "part 1", rule, show);
 
-    println!("# unique antinode locations in bounds (without resonant harmonics): {}", unique_locations.len());
+    println!("# unique antinode locations in bounds (without resonant harmonics): {}", unique_locations);
 }
 
-fn part2(network: &AntennaNetwork) {
-    let antinodes_in_bounds = network.get_antinodes_in_bounds(true);
-    let unique_locations = antinodes_in_bounds.iter().map(|(_, loc)| *loc)
-        .collect::<HashSet<(usize, usize)>>();
+fn part2(network: &AntennaNetwork, rule: &AntinodeRule, show: Show) {
+    let unique_locations = count_unique_locations(network, "part 2", rule, show);
 
-    println!("# unique antinode locations in bounds (with resonant harmonics): {}", unique_locations.len());
+    println!("# unique antinode locations in bounds (with resonant harmonics): {}", unique_locations);
 }
 
 fn main() -> Result<()> {
-    let file_path = util::file::get_input_file_path();
-    let lines = util::file::read_lines_raw(file_path)?;
-    let network = AntennaNetwork::from_lines(&lines);
+    let args = Args::parse();
+    let lines = util::file::read_lines_raw(args.file)?;
+    let network = AntennaNetwork::from_lines(&lines)?;
+
+    let custom_rule = match (args.ratios, args.harmonics) {
+        (Some(ratios), _) => Some(AntinodeRule::Ratios(ratios)),
+        (_, Some(every)) if every > 0 => Some(AntinodeRule::Harmonics{every}),
+        (_, Some(every)) => return Err(From::from(format!("Invalid harmonic step: {}", every))),
+        (None, None) => None,
+    };
+    let rules: Vec<(&str, AntinodeRule)> = match custom_rule {
+        Some(rule) => vec![("custom", rule)],
+        None => vec![
+            ("part 1", AntinodeRule::Ratios(vec![Ratio{num: 2, den: 1}])),
+            ("part 2", AntinodeRule::Harmonics{every: 1}),
+        ],
+    };
 
-    part1(&network);
-    part2(&network);
+    if let Some(ReportFormat::Json) = args.report {
+        println!("{}", json_report(&network, &rules));
+        return Ok(());
+    }
+
+    let show = Show{render: args.render, table: args.report.is_some()};
+    if let [(name, rule)] = rules.as_slice() {
+        let unique_locations = count_unique_locations(&network, name, rule, show);
+        println!("# unique antinode locations in bounds: {}", unique_locations);
+        return Ok(());
+    }
+
+    part1(&network, &rules[0].1, show);
+    part2(&network, &rules[1].1, show);
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "............
+........0...
+.....0......
+.......0....
+....0.......
+......A.....
+............
+............
+........A...
+.........A..
+............
+............";
+
+    fn network(text: &str) -> AntennaNetwork {
+        AntennaNetwork::from_lines(&text.lines().map(|line| line.to_owned()).collect::<Vec<String>>()).unwrap()
+    }
+
+    fn count(network: &AntennaNetwork, rule: AntinodeRule) -> usize {
+        count_unique_locations(network, "test", &rule, Show::default())
+    }
+
+    fn ratios(s: &str) -> AntinodeRule {
+        AntinodeRule::Ratios(s.split(',').map(|ratio| ratio.parse::<Ratio>().unwrap()).collect())
+    }
+
+    #[test]
+    fn example_counts() {
+        let network = network(EXAMPLE);
+        assert_eq!(network.bounds, vec![12, 12]);
+        assert_eq!(count(&network, ratios("2")), 14);
+        assert_eq!(count(&network, AntinodeRule::Harmonics{every: 1}), 34);
+    }
+
+    #[test]
+    fn ratios_parse_as_fractions() {
+        assert_eq!("2".parse::<Ratio>().unwrap(), Ratio{num: 2, den: 1});
+        assert_eq!(" -1/3 ".parse::<Ratio>().unwrap(), Ratio{num: -1, den: 3});
+        assert!("1/0".parse::<Ratio>().is_err());
+        assert!("1/".parse::<Ratio>().is_err());
+        assert!("x".parse::<Ratio>().is_err());
+    }
+
+    #[test]
+    fn ratios_only_land_on_whole_coordinates() {
+        // Antennas 3 apart along a row, and 2 apart
+        let network = network("a..a.b.b");
+        let antinodes = network.get_antinodes_in_bounds(&ratios("1/3,2/3"));
+        let mut points: Vec<(char, Point)> = antinodes.into_keys().collect();
+        points.sort();
+        assert_eq!(points, vec![('a', vec![0, 1]), ('a', vec![0, 2])]);
+
+        // Half way is only whole for the `b` pair, and -1 mirrors each antenna through the other,
+        // which only fits on the row once for each pair
+        assert_eq!(count(&network, ratios("1/2")), 1);
+        assert_eq!(count(&network, ratios("-1")), 2);
+    }
+
+    #[test]
+    fn harmonics_step_along_the_line() {
+        // Antennas at (0, 0) and (2, 4) are on the line through every (k, 2k)
+        let network = network("a.........\n..........\n....a.....\n..........\n..........");
+        assert_eq!(count(&network, AntinodeRule::Harmonics{every: 1}), 5);
+        assert_eq!(count(&network, AntinodeRule::Harmonics{every: 2}), 3);
+    }
+
+    #[test]
+    fn coordinates_in_any_dimension() {
+        let network = network("bounds (5,5,5)\nz (0,0,0)\nz (1,1,1)\n\ny (4,0,0)");
+        assert_eq!(network.bounds, vec![5, 5, 5]);
+        assert_eq!(count(&network, ratios("2")), 1);
+        assert_eq!(count(&network, AntinodeRule::Harmonics{every: 1}), 5);
+
+        // Bounds fit the antennas when left off
+        let network = AntennaNetwork::from_lines(&["a (1,7)".to_owned(), "a (3,2)".to_owned()]).unwrap();
+        assert_eq!(network.bounds, vec![4, 8]);
+
+        for text in ["a (1,2)\na (1,2,3)", "bounds (5,5)\na (1,2,3)", "ab (1,2)", "a (1,2", "a (1,x)", "(1,2)"] {
+            let lines: Vec<String> = text.lines().map(|line| line.to_owned()).collect();
+            assert!(AntennaNetwork::from_lines(&lines).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn antennas_in_the_same_place_make_no_line() {
+        let network = network("a (1,1)\na (1,1)\nbounds (3,3)");
+        assert_eq!(count(&network, AntinodeRule::Harmonics{every: 1}), 0);
+        assert_eq!(count(&network, ratios("2")), 1);
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::{BTreeMap, BTreeSet};
+use super::{AntennaNetwork, Antinodes, Point};
+
+struct FrequencyStats {
+    antennas: usize,
+    antinodes: usize,
+}
+
+// An antinode produced by at least one pair of same-frequency antennas
+struct AntinodeEntry {
+    frequency: char,
+    location: Point,
+    pairs: Vec<(Point, Point)>,
+    on_antennas: Vec<char>, // Frequencies of any antennas at the same location
+}
+
+pub struct AntinodeReport {
+    rule: String,
+    frequencies: BTreeMap<char, FrequencyStats>,
+    shared: BTreeMap<Point, Vec<char>>, // Locations with antinodes of more than one frequency
+    antinodes: Vec<AntinodeEntry>,
+}
+
+impl AntinodeReport {
+    pub fn new(rule: &str, network: &AntennaNetwork, antinodes: &Antinodes) -> AntinodeReport {
+        let mut antennas_at: BTreeMap<&Point, Vec<char>> = BTreeMap::new();
+        for antenna in network.antennas.values().flatten() {
+            antennas_at.entry(&antenna.location).or_default().push(antenna.frequency);
+        }
+
+        let mut frequencies: BTreeMap<char, FrequencyStats> = network.antennas.iter()
+            .map(|(&frequency, antennas)| (frequency, FrequencyStats{antennas: antennas.len(), antinodes: 0}))
+            .collect();
+        let mut frequencies_at: BTreeMap<Point, BTreeSet<char>> = BTreeMap::new();
+        let mut entries: Vec<AntinodeEntry> = vec![];
+        for ((frequency, location), pairs) in antinodes.iter() {
+            frequencies.get_mut(frequency).unwrap().antinodes += 1;
+            frequencies_at.entry(location.clone()).or_default().insert(*frequency);
+
+            let antennas = &network.antennas[frequency];
+            let mut pairs: Vec<(Point, Point)> = pairs.iter()
+                .map(|&(i, j)| (antennas[i].location.clone(), antennas[j].location.clone()))
+                .collect();
+            pairs.sort();
+
+            let mut on_antennas = antennas_at.get(location).cloned().unwrap_or_default();
+            on_antennas.sort();
+            entries.push(AntinodeEntry{frequency: *frequency, location: location.clone(), pairs, on_antennas});
+        }
+        entries.sort_by(|a, b| (a.frequency, &a.location).cmp(&(b.frequency, &b.location)));
+
+        let shared = frequencies_at.into_iter()
+            .filter(|(_, frequencies)| frequencies.len() > 1)
+            .map(|(location, frequencies)| (location, frequencies.into_iter().collect()))
+            .collect();
+
+        AntinodeReport{rule: rule.to_owned(), frequencies, shared, antinodes: entries}
+    }
+
+    pub fn to_table(&self) -> String {
+        let mut lines: Vec<String> = vec![
+            format!("Antinodes for {}", self.rule),
+            "frequency | antennas | antinodes".to_owned(),
+        ];
+        for (frequency, stats) in self.frequencies.iter() {
+            lines.push(format!("{:>9} | {:>8} | {:>9}", frequency, stats.antennas, stats.antinodes));
+        }
+
+        lines.push(format!("\nLocations shared by several frequencies ({}):", self.shared.len()));
+        for (location, frequencies) in self.shared.iter() {
+            lines.push(format!("  {} <- {}", format_point(location), join_chars(frequencies)));
+        }
+
+        let on_antennas: Vec<&AntinodeEntry> = self.antinodes.iter().filter(|entry| !entry.on_antennas.is_empty()).collect();
+        lines.push(format!("\nAntinodes on antennas ({}):", on_antennas.len()));
+        for entry in on_antennas {
+            lines.push(format!(
+                "  {} at {} is on antenna(s) {}",
+                entry.frequency, format_point(&entry.location), join_chars(&entry.on_antennas),
+            ));
+        }
+
+        lines.push("\nAntenna pairs producing each antinode:".to_owned());
+        for entry in self.antinodes.iter() {
+            let pairs = entry.pairs.iter()
+                .map(|(a, b)| format!("{}-{}", format_point(a), format_point(b)))
+                .collect::<Vec<String>>();
+            lines.push(format!("  {} at {} <- {}", entry.frequency, format_point(&entry.location), pairs.join(", ")));
+        }
+
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> String {
+        let frequencies = self.frequencies.iter().map(|(frequency, stats)| format!(
+            "{{\"frequency\":{},\"antennas\":{},\"antinodes\":{}}}", json_char(*frequency), stats.antennas, stats.antinodes,
+        )).collect::<Vec<String>>();
+
+        let shared = self.shared.iter().map(|(location, frequencies)| format!(
+            "{{\"location\":{},\"frequencies\":[{}]}}",
+            json_point(location), frequencies.iter().map(|&f| json_char(f)).collect::<Vec<String>>().join(","),
+        )).collect::<Vec<String>>();
+
+        let antinodes = self.antinodes.iter().map(|entry| {
+            let pairs = entry.pairs.iter()
+                .map(|(a, b)| format!("[{},{}]", json_point(a), json_point(b)))
+                .collect::<Vec<String>>();
+            let on_antennas = entry.on_antennas.iter().map(|&f| json_char(f)).collect::<Vec<String>>();
+            format!(
+                "{{\"frequency\":{},\"location\":{},\"pairs\":[{}],\"on_antennas\":[{}]}}",
+                json_char(entry.frequency), json_point(&entry.location), pairs.join(","), on_antennas.join(","),
+            )
+        }).collect::<Vec<String>>();
+
+        format!(
+            "{{\"rule\":\"{}\",\"frequencies\":[{}],\"shared\":[{}],\"antinodes\":[{}]}}",
+            self.rule, frequencies.join(","), shared.join(","), antinodes.join(","),
+        )
+    }
+}
+
+fn join_chars(chars: &[char]) -> String {
+    chars.iter().map(|c| c.to_string()).collect::<Vec<String>>().join(", ")
+}
+
+fn format_point(point: &Point) -> String {
+    format!("({})", point.iter().map(|coord| coord.to_string()).collect::<Vec<String>>().join(", "))
+}
+
+fn json_point(point: &Point) -> String {
+    format!("[{}]", point.iter().map(|coord| coord.to_string()).collect::<Vec<String>>().join(","))
+}
+
+// Frequencies can be any character, including ones JSON needs escaped
+fn json_char(c: char) -> String {
+    match c {
+        '"' => "\"\\\"\"".to_owned(),
+        '\\' => "\"\\\\\"".to_owned(),
+        c if c.is_control() => format!("\"\\u{:04x}\"", c as u32),
+        c => format!("\"{}\"", c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AntinodeRule, Ratio};
+
+    // a and b both put an antinode on the lone c antenna at 4
+    fn report() -> AntinodeReport {
+        let lines: Vec<String> = "a (0)\na (2)\nb (5)\nb (6)\nc (4)\nbounds (8)".lines().map(|line| line.to_owned()).collect();
+        let network = AntennaNetwork::from_lines(&lines).unwrap();
+        let antinodes = network.get_antinodes_in_bounds(&AntinodeRule::Ratios(vec![Ratio{num: 2, den: 1}]));
+        AntinodeReport::new("part 1", &network, &antinodes)
+    }
+
+    #[test]
+    fn shared_locations_and_antinodes_on_antennas() {
+        let table = report().to_table();
+        assert!(table.contains("        a |        2 |         1\n        b |        2 |         2\n        c |        1 |         0"), "{}", table);
+        assert!(table.contains("Locations shared by several frequencies (1):\n  (4) <- a, b"), "{}", table);
+        assert!(table.contains("Antinodes on antennas (2):\n  a at (4) is on antenna(s) c\n  b at (4) is on antenna(s) c"), "{}", table);
+        assert!(table.contains("  a at (4) <- (0)-(2)\n  b at (4) <- (5)-(6)\n  b at (7) <- (5)-(6)"), "{}", table);
+    }
+
+    #[test]
+    fn json_lists_the_same() {
+        assert_eq!(report().to_json(), concat!(
+            r#"{"rule":"part 1","#,
+            r#""frequencies":[{"frequency":"a","antennas":2,"antinodes":1},{"frequency":"b","antennas":2,"antinodes":2},{"frequency":"c","antennas":1,"antinodes":0}],"#,
+            r#""shared":[{"location":[4],"frequencies":["a","b"]}],"#,
+            r#""antinodes":[{"frequency":"a","location":[4],"pairs":[[[0],[2]]],"on_antennas":["c"]},"#,
+            r#"{"frequency":"b","location":[4],"pairs":[[[5],[6]]],"on_antennas":["c"]},"#,
+            r#"{"frequency":"b","location":[7],"pairs":[[[5],[6]]],"on_antennas":[]}]}"#,
+        ));
+    }
+
+    #[test]
+    fn json_escapes_frequencies() {
+        assert_eq!(json_char('a'), r#""a""#);
+        assert_eq!(json_char('"'), r#""\"""#);
+        assert_eq!(json_char('\\'), r#""\\""#);
+        assert_eq!(json_char('\t'), r#""\u0009""#);
+    }
+}
@@ -0,0 +1,228 @@
+// Free runs ordered by start, each subtree also knowing the longest run in it, for finding the
+// leftmost or rightmost run with room for some length in O(log n) while runs come and go. A treap,
+// since unlike MaxSegmentTree the set of starts isn't known up front (runs merge and split as files
+// move around).
+pub struct GapTree {
+    nodes: Vec<Node>, // Arena, with removed nodes reused from `free_nodes`
+    free_nodes: Vec<usize>,
+    root: usize,
+    seed: u64,
+}
+
+const NIL: usize = usize::MAX;
+
+struct Node {
+    start: usize,
+    length: usize,
+    longest: usize, // Longest run in the subtree rooted here
+    priority: u64,
+    left: usize,
+    right: usize,
+}
+
+impl GapTree {
+    pub fn new() -> GapTree {
+        GapTree{nodes: vec![], free_nodes: vec![], root: NIL, seed: 0}
+    }
+
+    // Whether the subtree has any run with room for `length` (an empty one doesn't, even for 0)
+    fn fits(&self, node: usize, length: usize) -> bool {
+        node != NIL && self.nodes[node].longest >= length
+    }
+
+    fn longest(&self, node: usize) -> usize {
+        if node == NIL { 0 } else { self.nodes[node].longest }
+    }
+
+    fn update(&mut self, node: usize) {
+        let (left, right) = (self.nodes[node].left, self.nodes[node].right);
+        self.nodes[node].longest = self.nodes[node].length.max(self.longest(left)).max(self.longest(right));
+    }
+
+    // SplitMix64, so the tree's shape only depends on the order of changes
+    fn next_priority(&mut self) -> u64 {
+        self.seed = self.seed.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.seed;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // Into the runs starting before `start` and the rest
+    fn split(&mut self, node: usize, start: usize) -> (usize, usize) {
+        if node == NIL {
+            return (NIL, NIL);
+        }
+
+        if self.nodes[node].start < start {
+            let (left, right) = self.split(self.nodes[node].right, start);
+            self.nodes[node].right = left;
+            self.update(node);
+            (node, right)
+        } else {
+            let (left, right) = self.split(self.nodes[node].left, start);
+            self.nodes[node].left = right;
+            self.update(node);
+            (left, node)
+        }
+    }
+
+    // Every run in `left` has to start before every run in `right`
+    fn merge(&mut self, left: usize, right: usize) -> usize {
+        if left == NIL || right == NIL {
+            return if left == NIL { right } else { left };
+        }
+
+        if self.nodes[left].priority > self.nodes[right].priority {
+            let merged = self.merge(self.nodes[left].right, right);
+            self.nodes[left].right = merged;
+            self.update(left);
+            left
+        } else {
+            let merged = self.merge(left, self.nodes[right].left);
+            self.nodes[right].left = merged;
+            self.update(right);
+            right
+        }
+    }
+
+    // There mustn't already be a run at `start`
+    pub fn insert(&mut self, start: usize, length: usize) {
+        let node = Node{start, length, longest: length, priority: self.next_priority(), left: NIL, right: NIL};
+        let idx = match self.free_nodes.pop() {
+            Some(idx) => {
+                self.nodes[idx] = node;
+                idx
+            },
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            },
+        };
+
+        let (left, right) = self.split(self.root, start);
+        let left = self.merge(left, idx);
+        self.root = self.merge(left, right);
+    }
+
+    pub fn remove(&mut self, start: usize) {
+        let (left, rest) = self.split(self.root, start);
+        let (removed, right) = self.split(rest, start + 1);
+        if removed != NIL {
+            self.free_nodes.push(removed);
+        }
+        self.root = self.merge(left, right);
+    }
+
+    // Leftmost run in the subtree with room for `length`, which has to be somewhere in it
+    fn leftmost_in(&self, mut node: usize, length: usize) -> usize {
+        loop {
+            let Node{left, right, length: node_length, ..} = self.nodes[node];
+            if self.fits(left, length) {
+                node = left;
+            } else if node_length >= length {
+                return node;
+            } else {
+                node = right;
+            }
+        }
+    }
+
+    fn rightmost_in(&self, mut node: usize, length: usize) -> usize {
+        loop {
+            let Node{left, right, length: node_length, ..} = self.nodes[node];
+            if self.fits(right, length) {
+                node = right;
+            } else if node_length >= length {
+                return node;
+            } else {
+                node = left;
+            }
+        }
+    }
+
+    // (start, length) of the leftmost run starting before `end` with room for `length`. Only ever
+    // goes down a single path: once a run starts before `end`, so does everything left of it.
+    pub fn leftmost_fit(&self, end: usize, length: usize) -> Option<(usize, usize)> {
+        let mut node = self.root;
+        while self.fits(node, length) {
+            let Node{start, length: node_length, left, right, ..} = self.nodes[node];
+            if start >= end {
+                node = left;
+            } else if self.fits(left, length) {
+                node = self.leftmost_in(left, length);
+                return Some((self.nodes[node].start, self.nodes[node].length));
+            } else if node_length >= length {
+                return Some((start, node_length));
+            } else {
+                node = right;
+            }
+        }
+
+        None
+    }
+
+    // (start, length) of the rightmost run starting at or after `from` with room for `length`
+    pub fn rightmost_fit(&self, from: usize, length: usize) -> Option<(usize, usize)> {
+        let mut node = self.root;
+        while self.fits(node, length) {
+            let Node{start, length: node_length, left, right, ..} = self.nodes[node];
+            if start < from {
+                node = right;
+            } else if self.fits(right, length) {
+                node = self.rightmost_in(right, length);
+                return Some((self.nodes[node].start, self.nodes[node].length));
+            } else if node_length >= length {
+                return Some((start, node_length));
+            } else {
+                node = left;
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn fits_match_a_linear_scan() {
+        let mut tree = GapTree::new();
+        let mut runs: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut state = 1u64;
+        let mut random = |n: u64| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((state >> 33) % n) as usize
+        };
+
+        for _ in 0..5000 {
+            let start = random(500);
+            if runs.remove(&start).is_some() {
+                tree.remove(start);
+            } else {
+                let length = random(40);
+                runs.insert(start, length);
+                tree.insert(start, length);
+            }
+
+            let (bound, length) = (random(520), random(45));
+            let leftmost = runs.range(..bound).find(|&(_, &run_length)| run_length >= length);
+            assert_eq!(tree.leftmost_fit(bound, length), leftmost.map(|(&start, &length)| (start, length)));
+            let rightmost = runs.range(bound..).rev().find(|&(_, &run_length)| run_length >= length);
+            assert_eq!(tree.rightmost_fit(bound, length), rightmost.map(|(&start, &length)| (start, length)));
+        }
+    }
+
+    #[test]
+    fn empty_tree_fits_nothing() {
+        let mut tree = GapTree::new();
+        assert_eq!(tree.leftmost_fit(usize::MAX, 0), None);
+        tree.insert(5, 3);
+        tree.remove(5);
+        tree.remove(7);
+        assert_eq!(tree.rightmost_fit(0, 0), None);
+    }
+}
@@ -1,144 +1,377 @@
 use util::res::Result;
 use util::file::GenericParseError;
-use std::iter;
-use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use clap::{Parser, ValueEnum};
+use strategy::StrategyKind;
+use segment_tree::MaxSegmentTree;
 
-// None = free space, Some(u32) = file with ID
+mod gap_tree;
+mod render;
+mod segment_tree;
+mod strategy;
+
+#[derive(Parser, Debug)]
+struct Args {
+    #[arg(short, long)]
+    file: PathBuf,
+
+    #[arg(long, value_enum, default_value = "dense")]
+    format: DiskFormat,
+
+    // Defragment with only this strategy instead of the two puzzle parts
+    #[arg(short, long, value_enum)]
+    strategy: Option<StrategyKind>,
+
+    // Print the disk in the puzzle's notation before and after defragmenting
+    #[arg(long)]
+    render: bool,
+
+    // List every file move as it happens (and the disk after it, with --render)
+    #[arg(long)]
+    trace: bool,
+
+    // Line up the compacted and best effort layouts block by block, marking where they differ
+    #[arg(long, conflicts_with = "strategy")]
+    diff: bool,
+}
+
+// How the lengths in a disk map are written. There's no telling the two apart from the input alone,
+// since a single comma-separated length (`12`) is also a valid dense map (`1`, `2`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum DiskFormat {
+    Dense, // The puzzle's one digit per length (`2333133121414131402`)
+    Comma, // Lengths of any size separated by commas (`2,3,3,3,13,3,...`)
+}
+
+// None = free space, Some(u32) = file with ID. Only built for rendering, since real disks can be
+// far too big to expand.
 type DiskBlock = Option<u32>;
 
+// Blocks are never expanded, so memory stays proportional to the number of runs however large the
+// lengths in the disk map are
 struct Disk {
-    blocks: Vec<DiskBlock>,
-    file_pointers: Vec<(usize, usize)>, // (start, length)
+    file_pointers: Vec<(usize, usize)>, // (start, length), indexed by file ID
     free_pointers: Vec<(usize, usize)>, // (start, length)
+    size: usize,
+}
+
+// `length` blocks of a file moved from `from` to `to` in one go, either the whole file or (when
+// compacting) a piece off its end
+struct FileMove {
+    file_id: u32,
+    from: usize,
+    to: usize,
+    length: usize,
+}
+
+struct DefragResult {
+    runs: Vec<(usize, usize, u32)>, // (start, length, file ID) of every contiguous piece of a file
+    size: usize,
+    blocks_moved: usize,
+    files_moved: usize,
+    moves: Vec<FileMove>, // In the order they were made
+}
+
+impl DefragResult {
+    fn to_blocks(&self) -> Vec<DiskBlock> {
+        let mut blocks: Vec<DiskBlock> = vec![None; self.size];
+        for &(start, length, file_id) in self.runs.iter() {
+            blocks[start..start + length].fill(Some(file_id));
+        }
+
+        blocks
+    }
+
+    // Share of the free space that lies outside the largest free run: 0 when all of it is in one
+    // contiguous run, approaching 1 as it gets scattered into many small gaps
+    fn fragmentation(&self) -> f64 {
+        let mut runs: Vec<(usize, usize)> = self.runs.iter().map(|&(start, length, _)| (start, length)).collect();
+        runs.sort();
+
+        let mut total_free = 0usize;
+        let mut largest_free = 0usize;
+        let mut end = 0usize; // End of the previous file run
+        for (start, length) in runs.into_iter().chain([(self.size, 0)]) {
+            total_free += start - end;
+            largest_free = largest_free.max(start - end);
+            end = start + length;
+        }
+
+        if total_free == 0 {
+            0.0
+        } else {
+            1.0 - largest_free as f64 / total_free as f64
+        }
+    }
+
+    // Sum of position * file ID over every block, taking each run at once as ID * (sum of an
+    // arithmetic series of positions)
+    fn checksum(&self) -> u128 {
+        self.runs.iter().map(|&(start, length, file_id)| {
+            let (start, length) = (start as u128, length as u128);
+            file_id as u128 * (start * length + length * length.saturating_sub(1) / 2)
+        }).sum()
+    }
 }
 
 impl Disk {
-    fn from_str(input: &String) -> Result<Disk> {
-        let mut disk = Disk{blocks: vec![], file_pointers: vec![], free_pointers: vec![]};
+    fn to_blocks(&self) -> Vec<DiskBlock> {
+        let mut blocks: Vec<DiskBlock> = vec![None; self.size];
+        for (file_id, &(start, length)) in self.file_pointers.iter().enumerate() {
+            blocks[start..start + length].fill(Some(file_id as u32));
+        }
 
-        let mut is_file: bool = true;
-        let mut file_id: u32 = 0;
-        for c in input.chars() {
-            let length = c.to_digit(10).ok_or_else(
-                || GenericParseError::ValueError(format!("Invalid character: {}", c).to_owned())
-            )? as usize;
+        blocks
+    }
+
+    fn from_str(input: &str, format: DiskFormat) -> Result<Disk> {
+        let input = input.trim();
+        let lengths: Vec<usize> = match format {
+            DiskFormat::Comma => input.split(',').map(|length| {
+                length.trim().parse::<usize>().map_err(
+                    |_| GenericParseError::ValueError(format!("Invalid length: {}", length.trim())).into()
+                )
+            }).collect::<Result<Vec<usize>>>()?,
+            DiskFormat::Dense => input.chars().map(|c| {
+                c.to_digit(10).map(|length| length as usize).ok_or_else(|| match c {
+                    ',' => GenericParseError::ValueError("Invalid character: , (use --format comma for comma-separated lengths)".to_owned()).into(),
+                    _ => GenericParseError::ValueError(format!("Invalid character: {}", c)).into(),
+                })
+            }).collect::<Result<Vec<usize>>>()?,
+        };
+
+        let mut disk = Disk{file_pointers: vec![], free_pointers: vec![], size: 0};
 
+        let mut is_file: bool = true;
+        for length in lengths {
             if is_file {
-                disk.file_pointers.push((disk.blocks.len(), length));
-                disk.blocks.extend(iter::repeat(DiskBlock::Some(file_id)).take(length));
-            } else {
-                if length > 0 { disk.free_pointers.push((disk.blocks.len(), length)); }
-                disk.blocks.extend(iter::repeat(DiskBlock::None).take(length));
-
-                // Increment file ID for next file blocks
-                file_id += 1;
+                disk.file_pointers.push((disk.size, length));
+            } else if length > 0 {
+                disk.free_pointers.push((disk.size, length));
             }
+            disk.size += length;
 
             // Switch from file to not file, or vice versa
             is_file ^= true;
         }
 
+        if disk.file_pointers.len() > u32::MAX as usize + 1 {
+            return Err(From::from(format!("Too many files for 32-bit IDs: {}", disk.file_pointers.len())));
+        }
+
         Ok(disk)
     }
 
-    fn defragment_compact(&self) -> Vec<DiskBlock> {
-        let mut out = self.blocks.clone();
+    // Pours the rightmost file blocks into the leftmost free runs, splitting files across runs as
+    // needed, until no free run is left of a file block
+    fn defragment_compact(&self) -> DefragResult {
+        let mut remaining: Vec<usize> = self.file_pointers.iter().map(|&(_, length)| length).collect();
+        let mut runs: Vec<(usize, usize, u32)> = vec![];
+        let mut moves: Vec<FileMove> = vec![];
+        let mut blocks_moved = 0usize;
+        let mut files_moved = 0usize;
 
-        let mut write_idx = 0usize;
-        let mut read_idx = self.blocks.len() - 1;
-        while read_idx > write_idx {
-            match (out[read_idx], out[write_idx]) {
-                (None, None) => {
-                    read_idx -= 1;
-                },
-                (None, Some(_)) => {
-                    write_idx += 1;
-                    read_idx -= 1;
-                },
-                (Some(file_id), None) => {
-                    out[write_idx] = DiskBlock::Some(file_id);
-                    out[read_idx] = DiskBlock::None;
+        let mut read_id = self.file_pointers.len();
+        'free: for &(free_start, free_length) in self.free_pointers.iter() {
+            let (mut write_idx, mut free_left) = (free_start, free_length);
+            while free_left > 0 {
+                // Skip over files that are empty or already moved entirely
+                while read_id > 0 && remaining[read_id - 1] == 0 {
+                    read_id -= 1;
+                }
+                if read_id == 0 || self.file_pointers[read_id - 1].0 < write_idx {
+                    break 'free;
+                }
 
-                    write_idx += 1;
-                    read_idx -= 1;
-                },
-                (Some(_), Some(_)) => {
-                    write_idx += 1;
-                },
+                let file_id = read_id - 1;
+                let moving = free_left.min(remaining[file_id]);
+                if remaining[file_id] == self.file_pointers[file_id].1 {
+                    files_moved += 1;
+                }
+                runs.push((write_idx, moving, file_id as u32));
+                remaining[file_id] -= moving;
+                moves.push(FileMove{
+                    file_id: file_id as u32,
+                    from: self.file_pointers[file_id].0 + remaining[file_id],
+                    to: write_idx,
+                    length: moving,
+                });
+                blocks_moved += moving;
+                write_idx += moving;
+                free_left -= moving;
             }
         }
 
-        out
-    }
-
-    fn defragment_best_effort(&self) -> Vec<DiskBlock> {
-        let mut free_blocks_by_size: HashMap<usize, BTreeSet<usize>> = self.free_pointers.iter().fold(HashMap::new(), |mut acc, &(start, length)| {
-            acc.entry(length).or_insert(BTreeSet::new()).insert(start);
-            acc
-        });
-        
-        let mut out = self.blocks.clone();
-        for &(file_start, file_length) in self.file_pointers.iter().rev() {
-            let mut earliest_adequate_free_chunk: Option<(usize, usize)> = None; // (start, length)
-            for free_length in file_length..=9 {
-                match free_blocks_by_size.get(&free_length).map(|start_indices| start_indices.first()).flatten() {
-                    Some(start_index) => {
-                        if earliest_adequate_free_chunk.is_none() || *start_index < earliest_adequate_free_chunk.unwrap().0 {
-                            earliest_adequate_free_chunk = Some((*start_index, free_length));
-                        }
-                    },
-                    None => {},
-                }
+        // Whatever is left of each file stays at the front of where it started
+        for (file_id, (&(file_start, _), &length)) in self.file_pointers.iter().zip(remaining.iter()).enumerate() {
+            if length > 0 {
+                runs.push((file_start, length, file_id as u32));
+            }
+        }
+
+        DefragResult{runs, size: self.size, blocks_moved, files_moved, moves}
+    }
+
+    fn defragment_best_effort(&self) -> DefragResult {
+        // Free runs stay in their original order as they fill up from the left, so the leftmost run
+        // with enough room is the leftmost slot in the tree holding a large enough length
+        let mut free_starts: Vec<usize> = self.free_pointers.iter().map(|&(start, _)| start).collect();
+        let mut free_lengths: Vec<usize> = self.free_pointers.iter().map(|&(_, length)| length).collect();
+        let mut free_index = MaxSegmentTree::new(&free_lengths);
+
+        let mut runs: Vec<(usize, usize, u32)> = vec![];
+        let mut moves: Vec<FileMove> = vec![];
+        let mut blocks_moved = 0usize;
+        let mut files_moved = 0usize;
+        for (file_id, &(file_start, file_length)) in self.file_pointers.iter().enumerate().rev() {
+            if file_length == 0 {
+                continue;
             }
 
             // If we found a free chunk that's further back than this file's start, move it
-            match earliest_adequate_free_chunk {
-                Some((free_start, free_length)) if free_start < file_start => {
-                    // Remove this free block from the free blocks by size, any leftover size becomes a new free block
-                    free_blocks_by_size.entry(free_length).and_modify(|e| { e.pop_first(); });
-                    if file_length < free_length {
-                        free_blocks_by_size.entry(free_length - file_length)
-                            .or_insert(BTreeSet::new()).insert(free_start + file_length);
-                    }
-
-                    // Swap entries from file to free block
-                    for i in 0..file_length {
-                        out.swap(free_start + i, file_start + i);
-                    }
+            match free_index.leftmost_at_least(file_length) {
+                Some(free_idx) if free_starts[free_idx] < file_start => {
+                    runs.push((free_starts[free_idx], file_length, file_id as u32));
+                    moves.push(FileMove{file_id: file_id as u32, from: file_start, to: free_starts[free_idx], length: file_length});
+
+                    // Any leftover size stays behind as a smaller free chunk
+                    free_lengths[free_idx] -= file_length;
+                    free_index.set(free_idx, free_lengths[free_idx]);
+                    free_starts[free_idx] += file_length;
+
+                    blocks_moved += file_length;
+                    files_moved += 1;
                 },
-                _ => {}
+                _ => runs.push((file_start, file_length, file_id as u32)),
             }
         }
 
-        out
+        DefragResult{runs, size: self.size, blocks_moved, files_moved, moves}
     }
+}
 
-    fn checksum(blocks: &Vec<DiskBlock>) -> u64 {
-        blocks.iter().enumerate().map(|(idx, block)| {
-            (block.unwrap_or(0) as u64) * (idx as u64)
-        }).sum()
+fn print_stats(result: &DefragResult) {
+    println!(
+        "  {} blocks moved, {} files moved, fragmentation ratio {:.4}",
+        result.blocks_moved, result.files_moved, result.fragmentation()
+    );
+}
+
+// Replays the moves onto the original disk, drawing it after each one if asked to (ending on the
+// defragmented disk). The blocks are only expanded for drawing; the listing itself works off the
+// moves and runs alone.
+fn print_trace(disk: &Disk, result: &DefragResult, render: bool) {
+    let mut drawing = render.then(|| {
+        let max_id = result.runs.iter().map(|&(_, _, file_id)| file_id).max().unwrap_or(0);
+        (disk.to_blocks(), render::id_width(max_id))
+    });
+    for (i, m) in result.moves.iter().enumerate() {
+        println!("  #{}: file {} from {} to {} (length {})", i + 1, m.file_id, m.from, m.to, m.length);
+        if let Some((blocks, width)) = drawing.as_mut() {
+            blocks[m.from..m.from + m.length].fill(None);
+            blocks[m.to..m.to + m.length].fill(Some(m.file_id));
+            println!("  {}", render::render(blocks, *width));
+        }
+    }
+}
+
+fn print_result(disk: &Disk, name: &str, result: &DefragResult, args: &Args) {
+    println!("Checksum of defragmented disk ({}): {}", name, result.checksum());
+    print_stats(result);
+    if args.trace {
+        print_trace(disk, result, args.render);
+    } else if args.render {
+        let blocks = result.to_blocks();
+        println!("  {}", render::render(&blocks, render::cell_width(&blocks)));
     }
 }
 
-fn part1(disk: &Disk) {
-    let checksum = Disk::checksum(&disk.defragment_compact());
+fn part1(disk: &Disk, args: &Args) -> DefragResult {
+    let result = disk.defragment_compact();
+    print_result(disk, "compaction", &result, args);
 
-    println!("Checksum of defragmented disk (compaction): {}", checksum);
+    result
 }
 
-fn part2(disk: &Disk) {
-    let checksum = Disk::checksum(&disk.defragment_best_effort());
+fn part2(disk: &Disk, args: &Args) -> DefragResult {
+    let result = disk.defragment_best_effort();
+    print_result(disk, "best effort", &result, args);
 
-    println!("Checksum of defragmented disk (best effort): {}", checksum);
+    result
 }
 
 fn main() -> Result<()> {
-    let file_path = util::file::get_input_file_path();
-    let contents = util::file::read_to_string(file_path)?;
-    let disk = Disk::from_str(&contents)?;
+    let args = Args::parse();
+    let contents = util::file::read_to_string(args.file.clone())?;
+    let disk = Disk::from_str(&contents, args.format)?;
 
-    part1(&disk);
-    part2(&disk);
+    if args.render {
+        let blocks = disk.to_blocks();
+        println!("Disk before defragmenting:");
+        println!("  {}", render::render(&blocks, render::cell_width(&blocks)));
+    }
+
+    if let Some(kind) = args.strategy {
+        let result = kind.strategy().defragment(&disk);
+        print_result(&disk, &format!("{:?}", kind), &result, &args);
+        return Ok(());
+    }
+
+    let compacted = part1(&disk, &args);
+    let best_effort = part2(&disk, &args);
+
+    if args.diff {
+        println!("{}", render::diff(("compaction", &compacted.to_blocks()), ("best effort", &best_effort.to_blocks())));
+    }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "2333133121414131402";
+
+    #[test]
+    fn example_checksums() {
+        let disk = Disk::from_str(EXAMPLE, DiskFormat::Dense).unwrap();
+        assert_eq!(disk.defragment_compact().checksum(), 1928);
+        assert_eq!(disk.defragment_best_effort().checksum(), 2858);
+        assert_eq!(StrategyKind::Compact.strategy().defragment(&disk).checksum(), 1928);
+        assert_eq!(StrategyKind::LeftmostFirst.strategy().defragment(&disk).checksum(), 2858);
+    }
+
+    #[test]
+    fn formats_only_parse_as_asked() {
+        // The same text is a different disk in each format
+        let dense = Disk::from_str("12", DiskFormat::Dense).unwrap();
+        assert_eq!((dense.file_pointers.clone(), dense.free_pointers.clone(), dense.size), (vec![(0, 1)], vec![(1, 2)], 3));
+        let comma = Disk::from_str("12", DiskFormat::Comma).unwrap();
+        assert_eq!((comma.file_pointers.clone(), comma.free_pointers.clone(), comma.size), (vec![(0, 12)], vec![], 12));
+
+        let comma = Disk::from_str("2, 13,1\n", DiskFormat::Comma).unwrap();
+        assert_eq!(comma.file_pointers, vec![(0, 2), (15, 1)]);
+        assert!(Disk::from_str("2,13,1", DiskFormat::Dense).is_err());
+        assert!(Disk::from_str("2,x", DiskFormat::Comma).is_err());
+    }
+
+    #[test]
+    fn strategies_agree_on_a_dense_and_a_comma_map() {
+        let dense = Disk::from_str(EXAMPLE, DiskFormat::Dense).unwrap();
+        let comma = Disk::from_str(&EXAMPLE.chars().map(|c| c.to_string()).collect::<Vec<String>>().join(","), DiskFormat::Comma).unwrap();
+        for kind in StrategyKind::value_variants() {
+            assert_eq!(kind.strategy().defragment(&dense).checksum(), kind.strategy().defragment(&comma).checksum());
+        }
+    }
+
+    #[test]
+    fn checksums_huge_disks_without_expanding_them() {
+        let disk = Disk::from_str("1000000000000,1000000000000,1000000000000", DiskFormat::Comma).unwrap();
+        let result = disk.defragment_best_effort();
+        assert_eq!(result.files_moved, 1);
+        // File 1 moves into the gap right after file 0, at positions 10^12 to 2 * 10^12 - 1
+        let n: u128 = 1_000_000_000_000;
+        assert_eq!(result.checksum(), n * n + n * (n - 1) / 2);
+    }
+}
@@ -0,0 +1,118 @@
+use super::DiskBlock;
+
+// Roughly how many characters of blocks to fit on each line of a diff
+const DIFF_LINE_WIDTH: usize = 72;
+
+// Characters needed per block, writing IDs in base 36 so that the puzzle's single-digit IDs keep
+// their usual notation and bigger IDs stay short
+pub fn cell_width(blocks: &[DiskBlock]) -> usize {
+    id_width(blocks.iter().flatten().copied().max().unwrap_or(0))
+}
+
+// Characters needed for the given ID, for when the blocks haven't been expanded
+pub fn id_width(max_id: u32) -> usize {
+    encode_id(max_id).len()
+}
+
+fn encode_id(mut file_id: u32) -> String {
+    let mut digits: Vec<char> = vec![];
+    loop {
+        digits.push(char::from_digit(file_id % 36, 36).unwrap());
+        file_id /= 36;
+        if file_id == 0 {
+            break;
+        }
+    }
+
+    digits.iter().rev().collect()
+}
+
+fn encode_block(block: DiskBlock, width: usize) -> String {
+    match block {
+        Some(file_id) => format!("{:0>width$}", encode_id(file_id)),
+        None => ".".repeat(width),
+    }
+}
+
+// Single-character blocks are run together as in the puzzle (`00...111...2...333.44.5555`), wider
+// ones get a space between them so they can still be told apart (`00 00 .. .. 0a 0a`)
+pub fn render(blocks: &[DiskBlock], width: usize) -> String {
+    let separator = if width > 1 { " " } else { "" };
+    blocks.iter().map(|&block| encode_block(block, width)).collect::<Vec<String>>().join(separator)
+}
+
+// Both layouts one above the other, wrapped into lines starting with the index of their first block,
+// with a row of `^` under every block that differs
+pub fn diff(left: (&str, &[DiskBlock]), right: (&str, &[DiskBlock])) -> String {
+    let (left_name, left_blocks) = left;
+    let (right_name, right_blocks) = right;
+    let width = cell_width(left_blocks).max(cell_width(right_blocks));
+    let separator = if width > 1 { " " } else { "" };
+    let per_line = (DIFF_LINE_WIDTH / (width + separator.len())).max(1);
+    let size = left_blocks.len().max(right_blocks.len());
+    let label_width = left_name.len().max(right_name.len());
+    let offset_width = size.to_string().len();
+
+    let differing = (0..size).filter(|&i| left_blocks.get(i) != right_blocks.get(i)).count();
+    let mut lines: Vec<String> = vec![
+        format!("{} vs {}, {} of {} blocks differ (marked ^):", left_name, right_name, differing, size),
+    ];
+    for start in (0..size).step_by(per_line) {
+        let end = (start + per_line).min(size);
+        let left_line = render(&left_blocks[start.min(left_blocks.len())..end.min(left_blocks.len())], width);
+        let right_line = render(&right_blocks[start.min(right_blocks.len())..end.min(right_blocks.len())], width);
+        let markers = (start..end).map(|i| {
+            if left_blocks.get(i) != right_blocks.get(i) { "^".repeat(width) } else { " ".repeat(width) }
+        }).collect::<Vec<String>>().join(separator);
+
+        lines.push(format!("{:>offset_width$}  {:<label_width$}  {}", start, left_name, left_line));
+        lines.push(format!("{:>offset_width$}  {:<label_width$}  {}", "", right_name, right_line));
+        lines.push(format!("{:>offset_width$}  {:<label_width$}  {}", "", "", markers).trim_end().to_owned());
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Disk, DiskFormat};
+
+    #[test]
+    fn renders_the_example_layouts() {
+        let disk = Disk::from_str("2333133121414131402", DiskFormat::Dense).unwrap();
+        assert_eq!(render(&disk.to_blocks(), 1), "00...111...2...333.44.5555.6666.777.888899");
+        assert_eq!(render(&disk.defragment_compact().to_blocks(), 1), "0099811188827773336446555566..............");
+        assert_eq!(render(&disk.defragment_best_effort().to_blocks(), 1), "00992111777.44.333....5555.6666.....8888..");
+    }
+
+    #[test]
+    fn wide_ids_are_padded_and_spaced() {
+        let blocks = [Some(0), Some(35), Some(36), None, Some(1295)];
+        assert_eq!(cell_width(&blocks), 2);
+        assert_eq!(render(&blocks, 2), "00 0z 10 .. zz");
+        assert_eq!(cell_width(&[Some(1296)]), 3);
+        assert_eq!(cell_width(&[None, None]), 1);
+        assert_eq!(id_width(35), 1);
+        assert_eq!(id_width(36), 2);
+    }
+
+    #[test]
+    fn diff_marks_differing_blocks() {
+        let left = [Some(0), None, Some(1), Some(1)];
+        let right = [Some(0), Some(1), Some(1)];
+        assert_eq!(diff(("before", &left), ("after", &right)), [
+            "before vs after, 2 of 4 blocks differ (marked ^):",
+            "0  before  0.11",
+            "   after   011",
+            "            ^ ^",
+        ].join("\n"));
+
+        // Wraps at 72 characters worth of blocks, numbering each line by its first block
+        let long: Vec<DiskBlock> = vec![Some(0); 100];
+        let lines: Vec<String> = diff(("a", &long), ("b", &long)).split('\n').map(|line| line.to_owned()).collect();
+        assert_eq!(lines.len(), 7);
+        assert!(lines[0].starts_with("a vs b, 0 of 100"));
+        assert!(lines[4].starts_with(" 72  a  0000") && lines[4].ends_with(&"0".repeat(28)));
+    }
+}
@@ -0,0 +1,68 @@
+// Max segment tree over a fixed number of slots, for finding the leftmost slot holding at least some
+// value in O(log n) while values change underneath it
+pub struct MaxSegmentTree {
+    leaves: usize, // Number of leaves, rounded up to a power of two
+    tree: Vec<usize>, // tree[1] is the root, tree[i] has children tree[2i] and tree[2i + 1]
+}
+
+impl MaxSegmentTree {
+    pub fn new(values: &[usize]) -> MaxSegmentTree {
+        let leaves = values.len().max(1).next_power_of_two();
+        let mut tree = vec![0usize; 2 * leaves];
+        tree[leaves..leaves + values.len()].copy_from_slice(values);
+        for i in (1..leaves).rev() {
+            tree[i] = tree[2 * i].max(tree[2 * i + 1]);
+        }
+
+        MaxSegmentTree{leaves, tree}
+    }
+
+    pub fn set(&mut self, idx: usize, value: usize) {
+        let mut i = self.leaves + idx;
+        self.tree[i] = value;
+        while i > 1 {
+            i /= 2;
+            self.tree[i] = self.tree[2 * i].max(self.tree[2 * i + 1]);
+        }
+    }
+
+    pub fn leftmost_at_least(&self, value: usize) -> Option<usize> {
+        if self.tree[1] < value {
+            return None;
+        }
+
+        // Descend towards the left child whenever it can satisfy the value
+        let mut i = 1usize;
+        while i < self.leaves {
+            i = if self.tree[2 * i] >= value { 2 * i } else { 2 * i + 1 };
+        }
+
+        Some(i - self.leaves)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_leftmost_slot_as_values_change() {
+        let mut values = vec![3, 1, 4, 1, 5, 9, 2, 6];
+        let mut tree = MaxSegmentTree::new(&values);
+        for (i, value) in [(5, 0), (2, 8), (7, 12), (0, 0)] {
+            values[i] = value;
+            tree.set(i, value);
+            for wanted in 0..=13 {
+                assert_eq!(tree.leftmost_at_least(wanted), values.iter().position(|&v| v >= wanted));
+            }
+        }
+    }
+
+    #[test]
+    fn handles_sizes_that_are_not_powers_of_two() {
+        let tree = MaxSegmentTree::new(&[1, 2, 3]);
+        assert_eq!(tree.leftmost_at_least(3), Some(2));
+        assert_eq!(tree.leftmost_at_least(4), None);
+        assert_eq!(MaxSegmentTree::new(&[]).leftmost_at_least(1), None);
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::{BTreeMap, BTreeSet};
+use clap::ValueEnum;
+use super::{DefragResult, Disk, FileMove};
+use super::gap_tree::GapTree;
+
+pub trait DefragStrategy {
+    fn defragment(&self, disk: &Disk) -> DefragResult;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum StrategyKind {
+    Compact,
+    LeftmostFirst,
+    BestFit,
+    WorstFit,
+    MultiPass,
+    MoveToEnd,
+}
+
+impl StrategyKind {
+    pub fn strategy(&self) -> Box<dyn DefragStrategy> {
+        match self {
+            StrategyKind::Compact => Box::new(Compact),
+            StrategyKind::LeftmostFirst => Box::new(LeftmostFirst),
+            StrategyKind::BestFit => Box::new(BestFit),
+            StrategyKind::WorstFit => Box::new(WorstFit),
+            StrategyKind::MultiPass => Box::new(MultiPass),
+            StrategyKind::MoveToEnd => Box::new(MoveToEnd),
+        }
+    }
+}
+
+// Fills the leftmost free blocks with the rightmost file blocks, splitting files as needed (part 1)
+pub struct Compact;
+
+impl DefragStrategy for Compact {
+    fn defragment(&self, disk: &Disk) -> DefragResult {
+        disk.defragment_compact()
+    }
+}
+
+// Whole files in descending ID order, each moved once into the leftmost gap that fits (part 2)
+pub struct LeftmostFirst;
+
+impl DefragStrategy for LeftmostFirst {
+    fn defragment(&self, disk: &Disk) -> DefragResult {
+        disk.defragment_best_effort()
+    }
+}
+
+// Like LeftmostFirst, but into the smallest gap that fits, to keep large gaps for large files
+pub struct BestFit;
+
+impl DefragStrategy for BestFit {
+    fn defragment(&self, disk: &Disk) -> DefragResult {
+        let mut mover = WholeFileMover::new(disk);
+        mover.pass((0..disk.file_pointers.len()).rev(), |free, file_start, file_length| {
+            free.best_fit(file_start, file_length)
+        });
+
+        mover.finish()
+    }
+}
+
+// Like LeftmostFirst, but into the largest gap, so that what's left of it is most likely to be useful
+pub struct WorstFit;
+
+impl DefragStrategy for WorstFit {
+    fn defragment(&self, disk: &Disk) -> DefragResult {
+        let mut mover = WholeFileMover::new(disk);
+        mover.pass((0..disk.file_pointers.len()).rev(), |free, file_start, file_length| {
+            free.worst_fit(file_start, file_length)
+        });
+
+        mover.finish()
+    }
+}
+
+// Repeats LeftmostFirst passes until nothing moves, since space freed up by moving a file can fit
+// files that were already passed over
+pub struct MultiPass;
+
+impl DefragStrategy for MultiPass {
+    fn defragment(&self, disk: &Disk) -> DefragResult {
+        let mut mover = WholeFileMover::new(disk);
+        while mover.pass((0..disk.file_pointers.len()).rev(), |free, file_start, file_length| {
+            free.by_start.leftmost_fit(file_start, file_length).map(|(start, _)| start)
+        }) > 0 {}
+
+        mover.finish()
+    }
+}
+
+// Mirror image of LeftmostFirst: whole files in ascending ID order, each packed against the end of
+// the rightmost gap after it that fits
+pub struct MoveToEnd;
+
+impl DefragStrategy for MoveToEnd {
+    fn defragment(&self, disk: &Disk) -> DefragResult {
+        let mut mover = WholeFileMover::new(disk);
+        mover.pass(0..disk.file_pointers.len(), |free, file_start, file_length| {
+            free.by_start.rightmost_fit(file_start, file_length).map(|(start, length)| start + length - file_length)
+        });
+
+        mover.finish()
+    }
+}
+
+// Free runs by start, merged with their neighbours as files move out of the way, and indexed both
+// by start and by length
+struct FreeSpace {
+    runs: BTreeMap<usize, usize>, // start -> length
+    by_start: GapTree,
+    by_length: BTreeSet<(usize, usize)>, // (length, start), only of the runs starting before `horizon`
+    horizon: usize,
+}
+
+impl FreeSpace {
+    fn new(runs: &[(usize, usize)]) -> FreeSpace {
+        let mut free = FreeSpace{runs: BTreeMap::new(), by_start: GapTree::new(), by_length: BTreeSet::new(), horizon: usize::MAX};
+        for &(start, length) in runs.iter() {
+            free.insert(start, length);
+        }
+
+        free
+    }
+
+    fn insert(&mut self, start: usize, length: usize) {
+        self.runs.insert(start, length);
+        self.by_start.insert(start, length);
+        if start < self.horizon {
+            self.by_length.insert((length, start));
+        }
+    }
+
+    fn remove(&mut self, start: usize) -> Option<usize> {
+        let length = self.runs.remove(&start)?;
+        self.by_start.remove(start);
+        self.by_length.remove(&(length, start));
+
+        Some(length)
+    }
+
+    // Stops indexing runs by length from `end` on. The best and worst fits are only ever asked for
+    // files right to left in a single pass, and no file later in the pass can use a gap after this
+    // one, so each run only has to be dropped from the index once.
+    fn lower_horizon(&mut self, end: usize) {
+        if end >= self.horizon {
+            return;
+        }
+
+        for (&start, &length) in self.runs.range(end..self.horizon) {
+            self.by_length.remove(&(length, start));
+        }
+        self.horizon = end;
+    }
+
+    // Start of the smallest gap before `end` with room for `length` blocks (the leftmost of those)
+    fn best_fit(&mut self, end: usize, length: usize) -> Option<usize> {
+        self.lower_horizon(end);
+        self.by_length.range((length, 0)..).next().map(|&(_, start)| start)
+    }
+
+    // Start of the largest gap before `end`, if it has room for `length` blocks (the leftmost of those)
+    fn worst_fit(&mut self, end: usize, length: usize) -> Option<usize> {
+        self.lower_horizon(end);
+        let &(largest, _) = self.by_length.last().filter(|&&(largest, _)| largest >= length)?;
+        self.by_length.range((largest, 0)..).next().map(|&(_, start)| start)
+    }
+
+    // Fills `length` blocks at `at`, which must be inside a single free run
+    fn take(&mut self, at: usize, length: usize) {
+        let (&run_start, &run_length) = self.runs.range(..=at).next_back().unwrap();
+        self.remove(run_start);
+        if at > run_start {
+            self.insert(run_start, at - run_start);
+        }
+        if run_start + run_length > at + length {
+            self.insert(at + length, run_start + run_length - at - length);
+        }
+    }
+
+    fn release(&mut self, mut start: usize, mut length: usize) {
+        if let Some((&prev_start, &prev_length)) = self.runs.range(..start).next_back() {
+            if prev_start + prev_length == start {
+                self.remove(prev_start);
+                start = prev_start;
+                length += prev_length;
+            }
+        }
+        if let Some(next_length) = self.remove(start + length) {
+            length += next_length;
+        }
+
+        self.insert(start, length);
+    }
+}
+
+// Shared bookkeeping for the strategies that move whole files at a time
+struct WholeFileMover {
+    size: usize,
+    files: Vec<(usize, usize)>, // Current (start, length) of each file, by ID
+    free: FreeSpace,
+    blocks_moved: usize,
+    moved: Vec<bool>,
+    moves: Vec<FileMove>,
+}
+
+impl WholeFileMover {
+    fn new(disk: &Disk) -> WholeFileMover {
+        WholeFileMover{
+            size: disk.size,
+            files: disk.file_pointers.clone(),
+            free: FreeSpace::new(&disk.free_pointers),
+            blocks_moved: 0,
+            moved: vec![false; disk.file_pointers.len()],
+            moves: vec![],
+        }
+    }
+
+    // Offers each file in turn to `pick`, which returns where to move it (if anywhere) given the
+    // free space, its start and its length. Returns how many files were moved.
+    fn pass<F>(&mut self, order: impl Iterator<Item = usize>, pick: F) -> usize
+    where F: Fn(&mut FreeSpace, usize, usize) -> Option<usize> {
+        let mut files_moved = 0usize;
+        for file_id in order {
+            let (file_start, file_length) = self.files[file_id];
+            if file_length == 0 {
+                continue;
+            }
+
+            if let Some(to) = pick(&mut self.free, file_start, file_length) {
+                self.free.take(to, file_length);
+                self.free.release(file_start, file_length);
+                self.files[file_id] = (to, file_length);
+                self.blocks_moved += file_length;
+                self.moved[file_id] = true;
+                self.moves.push(FileMove{file_id: file_id as u32, from: file_start, to, length: file_length});
+                files_moved += 1;
+            }
+        }
+
+        files_moved
+    }
+
+    fn finish(self) -> DefragResult {
+        DefragResult{
+            runs: self.files.iter().enumerate()
+                .filter(|&(_, &(_, length))| length > 0)
+                .map(|(file_id, &(start, length))| (start, length, file_id as u32))
+                .collect(),
+            size: self.size,
+            blocks_moved: self.blocks_moved,
+            files_moved: self.moved.iter().filter(|&&moved| moved).count(),
+            moves: self.moves,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DiskBlock, DiskFormat};
+
+    // (start, length) of every free gap, straight from the blocks
+    fn gaps(blocks: &[DiskBlock]) -> Vec<(usize, usize)> {
+        let mut ret: Vec<(usize, usize)> = vec![];
+        for (idx, block) in blocks.iter().enumerate() {
+            match (block, ret.last_mut()) {
+                (None, Some((start, length))) if *start + *length == idx => *length += 1,
+                (None, _) => ret.push((idx, 1)),
+                _ => {},
+            }
+        }
+
+        ret
+    }
+
+    // Moves whole files one block at a time, asking `pick` where to each time from a fresh scan
+    fn reference(disk: &Disk, ascending: bool, passes: usize, pick: impl Fn(&[(usize, usize)], usize, usize) -> Option<usize>) -> Vec<DiskBlock> {
+        let mut blocks = disk.to_blocks();
+        for _ in 0..passes {
+            let ids: Vec<usize> = if ascending { (0..disk.file_pointers.len()).collect() } else { (0..disk.file_pointers.len()).rev().collect() };
+            for file_id in ids {
+                let Some(start) = blocks.iter().position(|&block| block == Some(file_id as u32)) else { continue };
+                let length = disk.file_pointers[file_id].1;
+                if let Some(to) = pick(&gaps(&blocks), start, length) {
+                    blocks[start..start + length].fill(None);
+                    blocks[to..to + length].fill(Some(file_id as u32));
+                }
+            }
+        }
+
+        blocks
+    }
+
+    #[test]
+    fn indexed_strategies_match_scanning_the_blocks() {
+        let mut state = 9u64;
+        let mut random = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) % 10
+        };
+
+        for _ in 0..200 {
+            // Files are never empty in a real map, and an empty one would join the gaps either side of it
+            let map: String = (0..1 + random() * 4).map(|idx| char::from_digit(random().max(1 - idx % 2) as u32, 10).unwrap()).collect();
+            let disk = Disk::from_str(&map, DiskFormat::Dense).unwrap();
+            let before = |gaps: &[(usize, usize)], end: usize, length: usize| -> Vec<(usize, usize)> {
+                gaps.iter().copied().filter(|&(start, gap)| start < end && gap >= length).collect()
+            };
+
+            let best = reference(&disk, false, 1, |gaps, end, length| {
+                before(gaps, end, length).into_iter().min_by_key(|&(start, gap)| (gap, start)).map(|(start, _)| start)
+            });
+            assert_eq!(BestFit.defragment(&disk).to_blocks(), best, "{}", map);
+
+            let worst = reference(&disk, false, 1, |gaps, end, length| {
+                before(gaps, end, length).into_iter().min_by_key(|&(start, gap)| (usize::MAX - gap, start)).map(|(start, _)| start)
+            });
+            assert_eq!(WorstFit.defragment(&disk).to_blocks(), worst, "{}", map);
+
+            let multi = reference(&disk, false, disk.file_pointers.len() + 1, |gaps, end, length| {
+                before(gaps, end, length).first().map(|&(start, _)| start)
+            });
+            assert_eq!(MultiPass.defragment(&disk).to_blocks(), multi, "{}", map);
+
+            let to_end = reference(&disk, true, 1, |gaps, from, length| {
+                gaps.iter().rev().find(|&&(start, gap)| start >= from && gap >= length).map(|&(start, gap)| start + gap - length)
+            });
+            assert_eq!(MoveToEnd.defragment(&disk).to_blocks(), to_end, "{}", map);
+        }
+    }
+}
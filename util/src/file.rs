@@ -1,6 +1,6 @@
 use std::fmt;
 use std::fs::File;
-use std::io::{prelude::*, BufReader};
+use std::io::{self, prelude::*, BufReader};
 use std::path::PathBuf;
 use std::str::FromStr;
 use clap::Parser;
@@ -17,6 +17,16 @@ pub fn get_input_file_path() -> PathBuf {
     args.file
 }
 
+// Passing `-` as the input file reads from stdin instead, for piping in input from other tools
+pub fn open_reader(path: PathBuf) -> Result<Box<dyn BufRead>> {
+    if path.as_os_str() == "-" {
+        return Ok(Box::new(BufReader::new(io::stdin())));
+    }
+
+    let f = File::open(path)?;
+    Ok(Box::new(BufReader::new(f)))
+}
+
 pub fn read_to_string(path: PathBuf) -> Result<String> {
     let f = File::open(path)?;
     let mut reader = BufReader::new(f);